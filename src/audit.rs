@@ -0,0 +1,235 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+//! An audit log with a JSON line for every signature made, for keeping a record of what was
+//! signed, when and with which certificate.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use c2pa::{Reader, Signer};
+use openssl::{
+    hash::{hash, Hasher, MessageDigest},
+    x509::X509,
+};
+use serde::Serialize;
+
+use crate::{kms::hex, tsa::TsaSigner};
+
+/// How a manifest was added to the asset.
+pub(crate) enum Flow<'a> {
+    /// Embedded in the output asset.
+    Embedded,
+    /// Written to a sidecar .c2pa file next to the output asset.
+    Sidecar(&'a Path),
+    /// Referenced by a remote URL, and embedded too unless it is in a sidecar.
+    Remote {
+        url: &'a str,
+        sidecar: Option<&'a Path>,
+    },
+    /// Embedded in the init segment of fragmented content.
+    Fragment { fragments: usize },
+}
+
+/// One line of the audit log.
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    timestamp: String,
+    tool: &'static str,
+    tool_version: &'static str,
+    flow: &'static str,
+    input: PathBuf,
+    input_sha256: String,
+    output: PathBuf,
+    output_sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sidecar: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sidecar_sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote_url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fragments: Option<usize>,
+    manifest_label: Option<String>,
+    claim_generator: Option<String>,
+    alg: String,
+    signer: &'static str,
+    cert_sha256: Option<String>,
+    cert_subject: Option<String>,
+    tsa: Option<String>,
+}
+
+/// Appends a record of each signing operation to a JSON lines file.
+pub(crate) struct AuditLog<'a> {
+    path: PathBuf,
+    file: File,
+    signer: &'a TsaSigner<'a>,
+    signer_kind: &'static str,
+}
+
+impl<'a> AuditLog<'a> {
+    /// Opens the audit log at `path` for appending, creating it if needed, so that signing
+    /// doesn't start when the records can't be written.
+    ///
+    /// `signer_kind` names where the signature comes from: local, process, http or kms.
+    pub fn open(path: &Path, signer: &'a TsaSigner<'a>, signer_kind: &'static str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context(format!("Opening audit log {:?}", path))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            signer,
+            signer_kind,
+        })
+    }
+
+    /// Records the signing of `input` to `output`, with the manifest label and claim generator
+    /// read back from the signed `reader`.
+    pub fn record(
+        &self,
+        flow: Flow,
+        input: &Path,
+        output: &Path,
+        reader: Option<&Reader>,
+    ) -> Result<()> {
+        let (flow, sidecar, remote_url, fragments) = match flow {
+            Flow::Embedded => ("embedded", None, None, None),
+            Flow::Sidecar(sidecar) => ("sidecar", Some(sidecar), None, None),
+            Flow::Remote { url, sidecar } => ("remote", sidecar, Some(url), None),
+            Flow::Fragment { fragments } => ("fragment", None, None, Some(fragments)),
+        };
+        let cert = self
+            .signer
+            .certs()
+            .ok()
+            .and_then(|certs| certs.into_iter().next());
+        let manifest = reader.and_then(|r| r.active_manifest());
+
+        let record = AuditRecord {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            flow,
+            input: absolute(input),
+            input_sha256: file_sha256(input)?,
+            output: absolute(output),
+            output_sha256: file_sha256(output)?,
+            sidecar: sidecar.map(absolute),
+            sidecar_sha256: sidecar.map(file_sha256).transpose()?,
+            remote_url,
+            fragments,
+            manifest_label: manifest.and_then(|m| m.label()).map(str::to_owned),
+            claim_generator: manifest.map(|m| m.claim_generator().to_owned()),
+            alg: self.signer.alg().to_string(),
+            signer: self.signer_kind,
+            cert_sha256: cert
+                .as_ref()
+                .map(|der| hash(MessageDigest::sha256(), der).map(|h| hex(&h)))
+                .transpose()?,
+            cert_subject: cert.as_ref().and_then(|der| subject(der)),
+            tsa: self.signer.last_url(),
+        };
+
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        (&self.file)
+            .write_all(line.as_bytes())
+            .context(format!("Writing audit record to {:?}", self.path))
+    }
+}
+
+// the full path of a file, so records don't depend on the working directory
+fn absolute(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// the hex SHA-256 hash of a file's contents
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = File::open(path).context(format!("Hashing {:?}", path))?;
+    let mut hasher = Hasher::new(MessageDigest::sha256())?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let len = file.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buffer[..len])?;
+    }
+    Ok(hex(&hasher.finish()?))
+}
+
+// the subject of a DER certificate, like "O=C2PA Test Signing Cert, CN=C2PA Signer"
+fn subject(der: &[u8]) -> Option<String> {
+    let cert = X509::from_der(der).ok()?;
+    let entries: Vec<String> = cert
+        .subject_name()
+        .entries()
+        .filter_map(|entry| {
+            let name = entry.object().nid().short_name().ok()?;
+            let value = entry.data().as_utf8().ok()?;
+            Some(format!("{}={}", name, value))
+        })
+        .collect();
+    Some(entries.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use crate::signer::SignConfig;
+
+    #[test]
+    fn test_records_are_appended() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let input = tempdir.path().join("in.jpg");
+        let output = tempdir.path().join("out.jpg");
+        std::fs::write(&input, b"input").unwrap();
+        std::fs::write(&output, b"output").unwrap();
+
+        let sign_config = SignConfig::default();
+        let signer = sign_config.signer(&Default::default(), None).unwrap();
+        let tsa_signer = TsaSigner::new(signer.as_ref(), &sign_config);
+        let log_path = tempdir.path().join("audit.jsonl");
+        let log = AuditLog::open(&log_path, &tsa_signer, "local").unwrap();
+        log.record(Flow::Embedded, &input, &output, None).unwrap();
+        log.record(Flow::Fragment { fragments: 3 }, &input, &output, None)
+            .unwrap();
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&log_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["flow"], "embedded");
+        assert_eq!(
+            lines[0]["input_sha256"],
+            "c96c6d5be8d08a12e7b5cdc1b207fa6b2430974c86803d8891675e76fd992c20"
+        );
+        assert_eq!(lines[0]["alg"], "es256");
+        assert_eq!(lines[0]["signer"], "local");
+        assert_eq!(lines[0]["cert_sha256"].as_str().unwrap().len(), 64);
+        assert!(lines[0]["tsa"].is_null());
+        assert!(lines[0].get("fragments").is_none());
+        assert_eq!(lines[1]["flow"], "fragment");
+        assert_eq!(lines[1]["fragments"], 3);
+    }
+}
@@ -11,21 +11,111 @@
 // each license.
 
 use std::{
-    io::Write,
-    path::PathBuf,
+    io::{Read, Write},
+    path::{Path, PathBuf},
     process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, sleep, JoinHandle},
+    time::Duration,
 };
 
 use anyhow::{bail, Context};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use c2pa::{Error, Signer, SigningAlg};
+use clap::{Args, ValueEnum};
+use openssl::{
+    bn::BigNum,
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    pkey::PKey,
+    rsa::{Padding, Rsa},
+    sign::Verifier,
+    x509::X509,
+};
+use serde::{Deserialize, Serialize};
+use wait_timeout::ChildExt;
+
+use crate::{
+    preflight::{check_sign_cert, key_description, key_matches_alg},
+    signer::SignConfig,
+};
+
+/// The exit code (from sysexits.h) of a signer process whose failure is temporary, so that
+/// running it again may succeed.
+const EX_TEMPFAIL: i32 = 75;
+
+/// How data is exchanged with the external signer process.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SignerProtocol {
+    /// The claim bytes are written to stdin and the signature bytes are read from stdout.
+    #[default]
+    Raw,
+    /// A JSON request is written to stdin and a JSON response is read from stdout.
+    Json,
+}
+
+/// Information about what is being signed, sent to the external signer with the JSON protocol.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SignContext {
+    pub asset_path: Option<PathBuf>,
+}
+
+/// The request written to the external signer's stdin with the JSON protocol.
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonSignRequest<'a> {
+    /// Base64 encoded bytes to sign.
+    data: String,
+    alg: String,
+    sign_cert: &'a Path,
+    reserve_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asset_path: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manifest_label: Option<&'a str>,
+}
 
-use crate::signer::SignConfig;
+/// The response read from the external signer's stdout with the JSON protocol.
+#[derive(Debug, Deserialize)]
+struct JsonSignResponse {
+    /// Base64 encoded signature.
+    signature: String,
+    /// PEM certificate chain of the key that made the signature.
+    #[serde(default)]
+    certs: Option<String>,
+}
+
+/// Returns the label of the manifest whose claim is in the COSE `Sig_structure` being signed.
+///
+/// The claim links to its signature box with a URI like
+/// `self#jumbf=/c2pa/<manifest label>/c2pa.signature`.
+fn manifest_label(bytes: &[u8]) -> Option<String> {
+    let structure: ciborium::Value = ciborium::from_reader(bytes).ok()?;
+    // ["Signature1", protected header, external aad, payload]
+    let claim = structure.as_array()?.get(3)?.as_bytes()?;
+    let claim: ciborium::Value = ciborium::from_reader(claim.as_slice()).ok()?;
+    let signature = claim
+        .as_map()?
+        .iter()
+        .find(|(k, _)| k.as_text() == Some("signature"))?
+        .1
+        .as_text()?;
+    let label = signature
+        .strip_prefix("self#jumbf=/c2pa/")?
+        .strip_suffix("/c2pa.signature")?;
+    Some(label.to_owned())
+}
 
 /// A struct that implements [SignCallback]. This struct will call out to the client provided
 /// external signer to get the signed bytes for the asset.
 pub(crate) struct ExternalProcessRunner {
     config: CallbackSignerConfig,
     signer_path: PathBuf,
+    protocol: SignerProtocol,
+    context: SignContext,
+    process: SignerProcessArgs,
 }
 
 impl ExternalProcessRunner {
@@ -33,14 +123,136 @@ impl ExternalProcessRunner {
         Self {
             config,
             signer_path,
+            protocol: SignerProtocol::Raw,
+            context: SignContext::default(),
+            process: SignerProcessArgs::default(),
         }
     }
+
+    /// Sets the timeout, retries, extra arguments and environment for the external process.
+    pub fn with_process_args(mut self, process: SignerProcessArgs) -> Self {
+        self.process = process;
+        self
+    }
+
+    /// Sets the protocol used to talk to the external process and what is sent with it.
+    pub fn with_protocol(mut self, protocol: SignerProtocol, context: SignContext) -> Self {
+        self.protocol = protocol;
+        self.context = context;
+        self
+    }
+}
+
+/// Returns the JSON protocol request to sign `bytes`.
+pub(crate) fn json_request(
+    config: &CallbackSignerConfig,
+    context: &SignContext,
+    bytes: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let manifest_label = manifest_label(bytes);
+    let request = JsonSignRequest {
+        data: BASE64.encode(bytes),
+        alg: config.alg.to_string(),
+        sign_cert: &config.sign_cert_path,
+        reserve_size: config.reserve_size(),
+        asset_path: context.asset_path.as_deref(),
+        manifest_label: manifest_label.as_deref(),
+    };
+    Ok(serde_json::to_vec(&request)?)
+}
+
+/// Returns the signature in a JSON protocol response, checking any certificate chain sent with it.
+pub(crate) fn json_response(
+    config: &CallbackSignerConfig,
+    response: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let response: JsonSignResponse = serde_json::from_slice(response)
+        .context("The external signer did not write a valid JSON response")?;
+    let signature = BASE64
+        .decode(response.signature.trim())
+        .context("The signature in the external signer's response is not valid base64")?;
+
+    // the certificate chain is added to the manifest before the claim is signed, so
+    // a chain returned with the signature can only be checked against it
+    if let Some(certs) = response.certs {
+        let returned: Vec<Vec<u8>> = pem::parse_many(certs)
+            .context("The certificate chain in the external signer's response is not valid PEM")?
+            .into_iter()
+            .map(|p| p.into_contents())
+            .collect();
+        let configured = read_cert_chain(&config.sign_cert_path)?;
+        if returned != configured {
+            bail!(
+                "The external signer returned a certificate chain that differs from the sign_cert {:?}",
+                config.sign_cert_path
+            );
+        }
+    }
+    Ok(signature)
 }
 
 impl SignCallback for ExternalProcessRunner {
     /// Runs the client-provided [Command], passing to it, via stdin, the bytes to be signed. We
     /// also pass the `reserve-size`, `sign-cert`, and `alg` as CLI arguments to the [Command].
+    /// With the JSON protocol, the bytes are sent in a JSON request instead.
     fn sign(&self, bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let input = match self.protocol {
+            SignerProtocol::Raw => bytes.to_vec(),
+            SignerProtocol::Json => json_request(&self.config, &self.context, bytes)?,
+        };
+
+        let bytes = retry(
+            self.process.retries,
+            Duration::from_millis(self.process.retry_delay),
+            || self.run(&input),
+        )?;
+
+        if bytes.is_empty() {
+            bail!("User supplied process succeeded, but the external process did not write signature bytes to stdout");
+        }
+
+        match self.protocol {
+            SignerProtocol::Raw => Ok(bytes),
+            SignerProtocol::Json => json_response(&self.config, &bytes),
+        }
+    }
+}
+
+/// Calls `attempt` until it succeeds, retrying transient failures up to `retries` times
+/// and doubling the delay, starting at `delay`, before each retry.
+///
+/// `attempt` returns `Ok(Err(..))` with a message for a transient failure, and `Err(..)`
+/// for a failure that is reported at once.
+pub(crate) fn retry<F>(retries: u32, mut delay: Duration, mut attempt: F) -> anyhow::Result<Vec<u8>>
+where
+    F: FnMut() -> anyhow::Result<Result<Vec<u8>, String>>,
+{
+    let mut retried = 0;
+    loop {
+        match attempt()? {
+            Ok(bytes) => return Ok(bytes),
+            Err(failure) if retried < retries => {
+                retried += 1;
+                log::warn!(
+                    "{} Retrying in {:?} (retry {} of {})",
+                    failure,
+                    delay,
+                    retried,
+                    retries
+                );
+                sleep(delay);
+                delay *= 2;
+            }
+            Err(failure) => bail!(failure),
+        }
+    }
+}
+
+impl ExternalProcessRunner {
+    /// Runs the external process once, returning its stdout if it succeeded. Failures worth
+    /// retrying, where the process timed out or exited with [EX_TEMPFAIL], are returned as
+    /// `Ok(Err(..))`.
+    fn run(&self, input: &[u8]) -> anyhow::Result<Result<Vec<u8>, String>> {
         let sign_cert = self
             .config
             .sign_cert_path
@@ -53,40 +265,140 @@ impl SignCallback for ExternalProcessRunner {
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .args(["--reserve-size", &self.config.reserve_size.to_string()])
+            .args(["--reserve-size", &self.config.reserve_size().to_string()])
             .args(["--alg", &format!("{}", &self.config.alg)])
             .args(["--sign-cert", sign_cert])
+            .args(&self.process.extra_args)
+            .envs(self.process.env.iter().map(|(k, v)| (k, v)))
             .spawn()
             .context(format!("Failed to run command at {:?}", self.signer_path))?;
 
-        // Write claim bytes to spawned processes' `stdin`.
-        child
+        // Write claim bytes to spawned processes' `stdin` and read its output on separate
+        // threads, so a process that doesn't read all its input can't block us.
+        let mut stdin = child
             .stdin
             .take()
-            .context("Failed to access `stdin` of external process")?
-            .write_all(bytes)
-            .context("Failed to write data to the provided external process")?;
+            .context("Failed to access `stdin` of external process")?;
+        let input = input.to_vec();
+        let writer = thread::spawn(move || stdin.write_all(&input));
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
 
-        let output = child
-            .wait_with_output()
-            .context(format!("Failed to read stdout from {:?}", self.signer_path))?;
+        let status = match self.process.timeout {
+            Some(timeout) => {
+                let timeout = Duration::from_secs(timeout);
+                match child.wait_timeout(timeout)? {
+                    Some(status) => status,
+                    None => {
+                        child.kill()?;
+                        child.wait()?;
+                        return Ok(Err(format!(
+                            "User supplied signer process timed out after {:?}.",
+                            timeout
+                        )));
+                    }
+                }
+            }
+            None => child.wait()?,
+        };
+
+        let written = writer.join().unwrap_or(Ok(()));
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = String::from_utf8_lossy(&stderr.join().unwrap_or_default()).into_owned();
 
-        if !output.status.success() {
-            bail!(format!(
+        if !status.success() {
+            let failure = format!(
                 "User supplied signer process failed. It's stderr output was: \n{}",
-                String::from_utf8(output.stderr).unwrap_or_default()
-            ));
+                stderr
+            );
+            return match status.code() {
+                Some(EX_TEMPFAIL) => Ok(Err(failure)),
+                _ => bail!(failure),
+            };
         }
-
-        let bytes = output.stdout;
-        if bytes.is_empty() {
-            bail!("User supplied process succeeded, but the external process did not write signature bytes to stdout");
+        if !stderr.trim().is_empty() {
+            log::info!(
+                "User supplied signer process stderr output: {}",
+                stderr.trim()
+            );
+        }
+        // a process that succeeded without reading all of its input is fine
+        if let Err(e) = written {
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
+                return Err(e).context("Failed to write data to the provided external process");
+            }
         }
 
-        Ok(bytes)
+        Ok(Ok(stdout))
     }
 }
 
+// reads a child process pipe to the end on another thread
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+fn parse_env_var(s: &str) -> anyhow::Result<(String, String)> {
+    let (key, value) = s.split_once('=').context("expected KEY=VALUE")?;
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// Options for running the external signer process.
+#[derive(Args, Clone, Debug, Default)]
+pub(crate) struct SignerProcessArgs {
+    /// Seconds to wait for the signer_path executable or signer_url service before giving up.
+    /// By default, waits until the executable exits, or 30 seconds for the service.
+    #[clap(
+        long = "signer-timeout",
+        value_name = "SECONDS",
+        requires = "external_signer"
+    )]
+    pub timeout: Option<u64>,
+
+    /// Number of times to run the signer_path executable or call the signer_url service again
+    /// after a transient failure: a timeout, an executable that exits with code 75
+    /// (EX_TEMPFAIL), or a service that can't be reached or has a server error.
+    #[clap(
+        long = "signer-retries",
+        default_value_t = 0,
+        requires = "external_signer"
+    )]
+    pub retries: u32,
+
+    /// Milliseconds to wait before the first retry. The wait doubles after each retry.
+    #[clap(
+        long = "signer-retry-delay",
+        value_name = "MS",
+        default_value_t = 500,
+        requires = "external_signer"
+    )]
+    pub retry_delay: u64,
+
+    /// Extra argument to pass to the signer_path executable. Can be repeated.
+    #[clap(
+        long = "signer-arg",
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        requires = "signer_path"
+    )]
+    pub extra_args: Vec<String>,
+
+    /// Environment variable to set for the signer_path executable, as KEY=VALUE. Can be repeated.
+    #[clap(
+        long = "signer-env",
+        value_name = "KEY=VALUE",
+        value_parser = parse_env_var,
+        requires = "signer_path"
+    )]
+    pub env: Vec<(String, String)>,
+}
+
 /// A config containing the required values for signing an asset with an external command.
 #[derive(Clone, Debug)]
 pub(crate) struct CallbackSignerConfig {
@@ -96,15 +408,24 @@ pub(crate) struct CallbackSignerConfig {
     pub alg: SigningAlg,
     /// A path to a file containing the signing cert required for signing
     pub sign_cert_path: PathBuf,
-    /// Size of the claim bytes.
-    pub reserve_size: usize,
+    /// Size reserved for the COSE signature. Shared between clones, so the external process
+    /// is told about a reserve size computed or grown by the [CallbackSigner].
+    reserve_size: Arc<AtomicUsize>,
+    /// Whether the reserve size is computed from the certificate chain and TSA, and grown if
+    /// the signature doesn't fit.
+    pub auto_reserve_size: bool,
     pub tsa_url: Option<String>,
 }
 
 impl CallbackSignerConfig {
     /// Constructs a new [CallbackSignerConfig] using a manifest sign config, the name of an
-    /// external process, and the reserve_size.
-    pub fn new(sign_config: &SignConfig, reserve_size: usize) -> anyhow::Result<Self> {
+    /// external process, and the reserve_size. If no reserve_size is given, it is computed
+    /// when signing. The signing certificate is checked against `trust_config`.
+    pub fn new(
+        sign_config: &SignConfig,
+        reserve_size: Option<usize>,
+        trust_config: Option<&str>,
+    ) -> anyhow::Result<Self> {
         let alg = sign_config
             .alg
             .clone()
@@ -116,13 +437,53 @@ impl CallbackSignerConfig {
             .clone()
             .context("Unable to load the provided sign_cert_path")?;
 
+        // there's no private key to check, since the signature is made elsewhere
+        let certs = std::fs::read(&sign_cert_path)
+            .context(format!("Reading sign cert: {:?}", &sign_cert_path))?;
+        check_sign_cert(
+            &format!("{:?}", sign_cert_path),
+            &certs,
+            None,
+            alg,
+            trust_config,
+        )?;
+
         Ok(CallbackSignerConfig {
             alg,
             sign_cert_path,
-            reserve_size,
-            tsa_url: sign_config.ta_url.clone(),
+            reserve_size: Arc::new(AtomicUsize::new(reserve_size.unwrap_or_default())),
+            auto_reserve_size: reserve_size.is_none(),
+            tsa_url: sign_config.tsa_urls().into_iter().next(),
         })
     }
+
+    pub fn reserve_size(&self) -> usize {
+        self.reserve_size.load(Ordering::SeqCst)
+    }
+
+    pub fn set_reserve_size(&self, reserve_size: usize) {
+        self.reserve_size.store(reserve_size, Ordering::SeqCst);
+    }
+}
+
+/// Reads the DER certificates of a PEM certificate chain file.
+fn read_cert_chain(path: &Path) -> c2pa::Result<Vec<Vec<u8>>> {
+    let cert_contents =
+        std::fs::read(path).map_err(|_| Error::FileNotFound(format!("{:?}", path)))?;
+
+    let mut pems = pem::parse_many(cert_contents).map_err(|_| Error::CoseInvalidCert)?;
+    // [pem::parse_many] returns an empty vector if you supply invalid contents, like json, for example.
+    // Check here if the pems vector is empty.
+    if pems.is_empty() {
+        return Err(Error::CoseInvalidCert);
+    }
+
+    let sign_cert = pems
+        .drain(..)
+        .map(|p| p.into_contents())
+        .collect::<Vec<Vec<u8>>>();
+
+    Ok(sign_cert)
 }
 
 #[cfg_attr(test, mockall::automock)]
@@ -132,6 +493,15 @@ pub(crate) trait SignCallback {
     fn sign(&self, data: &[u8]) -> anyhow::Result<Vec<u8>>;
 }
 
+/// Bytes reserved for the signature itself and the rest of the COSE structure.
+const SIGNATURE_OVERHEAD: usize = 1024;
+
+/// Assumed size of a time stamp response when the TSA can't be reached to measure one.
+const TSA_RESPONSE_SIZE: usize = 8192;
+
+/// How many times signing is retried with a doubled, computed reserve size.
+const RESERVE_SIZE_RETRIES: usize = 3;
+
 /// A [Signer] implementation that allows clients to provide their own function
 /// to sign the manifest bytes.
 pub(crate) struct CallbackSigner<'a> {
@@ -140,17 +510,162 @@ pub(crate) struct CallbackSigner<'a> {
 }
 
 impl<'a> CallbackSigner<'a> {
-    pub fn new(callback: Box<impl SignCallback + 'a>, config: CallbackSignerConfig) -> Self {
+    pub fn new(callback: Box<dyn SignCallback + 'a>, config: CallbackSignerConfig) -> Self {
         Self { callback, config }
     }
 }
 
+impl CallbackSigner<'_> {
+    /// Computes the reserve size from the certificate chain and, if a TSA is configured, the
+    /// size of a time stamp response from it.
+    fn estimate_reserve_size(&self) -> usize {
+        let certs_size: usize = read_cert_chain(&self.config.sign_cert_path)
+            .map(|certs| certs.iter().map(|c| c.len()).sum())
+            .unwrap_or_default();
+        let tsa_size = match self.send_timestamp_request(b"c2patool reserve size probe") {
+            Some(Ok(response)) => response.len(),
+            Some(Err(e)) => {
+                log::warn!(
+                    "Unable to get a time stamp to size the signature, assuming {} bytes: {}",
+                    TSA_RESPONSE_SIZE,
+                    e
+                );
+                TSA_RESPONSE_SIZE
+            }
+            None => 0,
+        };
+        SIGNATURE_OVERHEAD + certs_size + tsa_size
+    }
+
+    /// Runs `sign`, and if the reserve size is computed and the signature didn't fit, runs it
+    /// again with a larger reserve size.
+    pub fn retry_reserve_size<T>(
+        &self,
+        mut sign: impl FnMut() -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut attempt = 0;
+        loop {
+            match sign() {
+                Err(e)
+                    if self.config.auto_reserve_size
+                        && attempt < RESERVE_SIZE_RETRIES
+                        && matches!(e.downcast_ref(), Some(Error::CoseSigboxTooSmall)) =>
+                {
+                    attempt += 1;
+                    let reserve_size = self.config.reserve_size();
+                    log::warn!(
+                        "The signature didn't fit in the reserve size of {} bytes, retrying with {} bytes",
+                        reserve_size,
+                        reserve_size * 2
+                    );
+                    self.config.set_reserve_size(reserve_size * 2);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Checks that a signature returned by the callback is one the manifest can be embedded with:
+    /// it fits in the reserved space and verifies with the public key of the sign_cert.
+    fn check_signature(&self, data: &[u8], signature: &[u8]) -> anyhow::Result<()> {
+        let alg = self.config.alg;
+        let path = &self.config.sign_cert_path;
+        let certs = read_cert_chain(path)?;
+
+        // the COSE signature holds the certificate chain as well as the signature itself
+        let certs_size: usize = certs.iter().map(|c| c.len()).sum();
+        if signature.len() + certs_size > self.config.reserve_size() {
+            bail!(
+                "The {} byte signature and the {} byte certificate chain don't fit in the reserve size of {} bytes. Increase --reserve-size.",
+                signature.len(),
+                certs_size,
+                self.config.reserve_size()
+            );
+        }
+
+        let cert = X509::from_der(&certs[0]).context(format!(
+            "The sign_cert {:?} is not a valid certificate",
+            path
+        ))?;
+        let key = cert.public_key()?;
+        if !key_matches_alg(&key, alg) {
+            bail!(
+                "The sign_cert {:?} has an {} key, which can't be used with alg {}",
+                path,
+                key_description(&key),
+                alg
+            );
+        }
+        let verified = match alg {
+            SigningAlg::Es256 | SigningAlg::Es384 | SigningAlg::Es512 => {
+                let (digest, size) = match alg {
+                    SigningAlg::Es256 => (MessageDigest::sha256(), 32),
+                    SigningAlg::Es384 => (MessageDigest::sha384(), 48),
+                    _ => (MessageDigest::sha512(), 66),
+                };
+                // c2pa accepts ECDSA signatures as raw r||s or DER, converting DER itself
+                let der = if signature.len() == 2 * size {
+                    let r = BigNum::from_slice(&signature[..size])?;
+                    let s = BigNum::from_slice(&signature[size..])?;
+                    EcdsaSig::from_private_components(r, s)?.to_der()?
+                } else if EcdsaSig::from_der(signature).is_ok() {
+                    signature.to_vec()
+                } else {
+                    bail!(
+                        "The {} byte signature is not a valid {} signature. Expected {} bytes of r||s or a DER encoded signature.",
+                        signature.len(),
+                        alg,
+                        2 * size
+                    );
+                };
+                Verifier::new(digest, &key)?.verify_oneshot(&der, data)
+            }
+            SigningAlg::Ps256 | SigningAlg::Ps384 | SigningAlg::Ps512 => {
+                let digest = match alg {
+                    SigningAlg::Ps256 => MessageDigest::sha256(),
+                    SigningAlg::Ps384 => MessageDigest::sha384(),
+                    _ => MessageDigest::sha512(),
+                };
+                // rebuild the key from its components so RSA-PSS keys can be used too
+                let rsa = Rsa::public_key_from_der(&key.public_key_to_der()?)?;
+                let rsa = Rsa::from_public_components(rsa.n().to_owned()?, rsa.e().to_owned()?)?;
+                let key = PKey::from_rsa(rsa)?;
+                let mut verifier = Verifier::new(digest, &key)?;
+                verifier.set_rsa_padding(Padding::PKCS1_PSS)?;
+                verifier.set_rsa_mgf1_md(digest)?;
+                verifier.verify_oneshot(signature, data)
+            }
+            SigningAlg::Ed25519 => {
+                Verifier::new_without_digest(&key)?.verify_oneshot(signature, data)
+            }
+        };
+
+        if !verified.unwrap_or(false) {
+            bail!(
+                "The signature does not verify with the public key of the sign_cert {:?} using alg {}. Check that it was made with the private key of that certificate.",
+                path,
+                alg
+            );
+        }
+        Ok(())
+    }
+}
+
 impl Signer for CallbackSigner<'_> {
     fn sign(&self, data: &[u8]) -> c2pa::Result<Vec<u8>> {
-        self.callback.sign(data).map_err(|e| {
+        let signature = self.callback.sign(data).map_err(|e| {
             eprintln!("Unable to embed signature into asset. {}", e);
             Error::EmbeddingError
-        })
+        })?;
+        if self.config.auto_reserve_size {
+            let certs_size: usize = self.certs()?.iter().map(|c| c.len()).sum();
+            if signature.len() + certs_size > self.config.reserve_size() {
+                return Err(Error::CoseSigboxTooSmall);
+            }
+        }
+        self.check_signature(data, &signature)
+            .map_err(|e| Error::OtherError(e.into()))?;
+        Ok(signature)
     }
 
     fn alg(&self) -> SigningAlg {
@@ -158,26 +673,15 @@ impl Signer for CallbackSigner<'_> {
     }
 
     fn certs(&self) -> c2pa::Result<Vec<Vec<u8>>> {
-        let cert_contents = std::fs::read(&self.config.sign_cert_path)
-            .map_err(|_| Error::FileNotFound(format!("{:?}", self.config.sign_cert_path)))?;
-
-        let mut pems = pem::parse_many(cert_contents).map_err(|_| Error::CoseInvalidCert)?;
-        // [pem::parse_many] returns an empty vector if you supply invalid contents, like json, for example.
-        // Check here if the pems vector is empty.
-        if pems.is_empty() {
-            return Err(Error::CoseInvalidCert);
-        }
-
-        let sign_cert = pems
-            .drain(..)
-            .map(|p| p.into_contents())
-            .collect::<Vec<Vec<u8>>>();
-
-        Ok(sign_cert)
+        read_cert_chain(&self.config.sign_cert_path)
     }
 
     fn reserve_size(&self) -> usize {
-        self.config.reserve_size
+        // computed once, when signing first needs it
+        if self.config.auto_reserve_size && self.config.reserve_size() == 0 {
+            self.config.set_reserve_size(self.estimate_reserve_size());
+        }
+        self.config.reserve_size()
     }
 
     fn time_authority_url(&self) -> Option<String> {
@@ -188,9 +692,38 @@ impl Signer for CallbackSigner<'_> {
 #[cfg(test)]
 mod test {
     use anyhow::anyhow;
+    use c2pa::Builder;
 
     use super::*;
 
+    // signs `data` with one of the sample private keys, as an external signer would
+    fn sample_sign(key: &str, alg: SigningAlg, data: &[u8]) -> Vec<u8> {
+        let mut key_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        key_path.push("sample");
+        key_path.push(key);
+        let key = PKey::private_key_from_pem(&std::fs::read(key_path).unwrap()).unwrap();
+        match alg {
+            SigningAlg::Ps256 => {
+                let mut signer = openssl::sign::Signer::new(MessageDigest::sha256(), &key).unwrap();
+                signer.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+                signer.set_rsa_mgf1_md(MessageDigest::sha256()).unwrap();
+                signer.sign_oneshot_to_vec(data).unwrap()
+            }
+            _ => openssl::sign::Signer::new(MessageDigest::sha256(), &key)
+                .unwrap()
+                .sign_oneshot_to_vec(data)
+                .unwrap(),
+        }
+    }
+
+    fn mock_signer(config: CallbackSignerConfig, signature: Vec<u8>) -> CallbackSigner<'static> {
+        let mut mock_callback_signer = MockSignCallback::default();
+        mock_callback_signer
+            .expect_sign()
+            .returning(move |_| Ok(signature.clone()));
+        CallbackSigner::new(Box::new(mock_callback_signer), config)
+    }
+
     #[test]
     fn test_signing_succeeds_returns_bytes() {
         let mut sign_cert_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -202,7 +735,7 @@ mod test {
             ..Default::default()
         };
 
-        let result = vec![1, 2, 3];
+        let result = sample_sign("es256_private.key", SigningAlg::Es256, b"claim");
         let expected = result.clone();
 
         let mut mock_callback_signer = MockSignCallback::default();
@@ -210,11 +743,78 @@ mod test {
             .expect_sign()
             .returning(move |_| Ok(result.clone()));
 
-        let config = CallbackSignerConfig::new(&sign_config, 1024).unwrap();
+        let config = CallbackSignerConfig::new(&sign_config, Some(10248), None).unwrap();
         let callback = Box::new(mock_callback_signer);
         let signer = CallbackSigner::new(callback, config);
 
-        assert_eq!(signer.sign(&[]).unwrap(), expected);
+        assert_eq!(signer.sign(b"claim").unwrap(), expected);
+    }
+
+    #[test]
+    fn test_signing_accepts_raw_ecdsa_signature() {
+        let der = sample_sign("es256_private.key", SigningAlg::Es256, b"claim");
+        let sig = EcdsaSig::from_der(&der).unwrap();
+        let mut raw = sig.r().to_vec_padded(32).unwrap();
+        raw.extend(sig.s().to_vec_padded(32).unwrap());
+
+        let config = es256_config();
+        config.set_reserve_size(10248);
+        let signer = mock_signer(config, raw.clone());
+        assert_eq!(signer.sign(b"claim").unwrap(), raw);
+    }
+
+    #[test]
+    fn test_signing_accepts_ps256_signature() {
+        let mut sign_cert_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sign_cert_path.push("sample/ps256.pub");
+        let sign_config = SignConfig {
+            alg: Some(SigningAlg::Ps256.to_string()),
+            sign_cert: Some(sign_cert_path),
+            ..Default::default()
+        };
+        let config = CallbackSignerConfig::new(&sign_config, Some(10248), None).unwrap();
+
+        let signature = sample_sign("ps256.pem", SigningAlg::Ps256, b"claim");
+        let signer = mock_signer(config, signature.clone());
+        assert_eq!(signer.sign(b"claim").unwrap(), signature);
+    }
+
+    #[test]
+    fn test_signing_rejects_signature_of_other_data() {
+        let config = es256_config();
+        config.set_reserve_size(10248);
+        let signature = sample_sign("es256_private.key", SigningAlg::Es256, b"other claim");
+        let error = mock_signer(config, signature).sign(b"claim").unwrap_err();
+        assert!(error.to_string().contains("does not verify"));
+
+        let config = es256_config();
+        config.set_reserve_size(10248);
+        let error = mock_signer(config, vec![1, 2, 3])
+            .sign(b"claim")
+            .unwrap_err();
+        assert!(error.to_string().contains("not a valid es256 signature"));
+    }
+
+    #[test]
+    fn test_signing_rejects_alg_not_matching_key() {
+        let mut config = es256_config();
+        config.set_reserve_size(10248);
+        config.alg = SigningAlg::Es384;
+        let signature = sample_sign("es256_private.key", SigningAlg::Es256, b"claim");
+        let error = mock_signer(config, signature).sign(b"claim").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("has an EC P-256 key, which can't be used with alg es384"));
+    }
+
+    #[test]
+    fn test_signing_rejects_signature_larger_than_reserve_size() {
+        // the certificate chain alone doesn't fit in 1024 bytes
+        let signature = sample_sign("es256_private.key", SigningAlg::Es256, b"claim");
+        let error = mock_signer(es256_config(), signature)
+            .sign(b"claim")
+            .unwrap_err();
+        assert!(error.to_string().contains("reserve size of 1024 bytes"));
     }
 
     #[test]
@@ -233,7 +833,7 @@ mod test {
             .expect_sign()
             .returning(|_| Err(anyhow!("")));
 
-        let config = CallbackSignerConfig::new(&sign_config, 1024).unwrap();
+        let config = CallbackSignerConfig::new(&sign_config, Some(1024), None).unwrap();
         let callback = Box::new(mock_callback_signer);
         let signer = CallbackSigner::new(callback, config);
 
@@ -243,7 +843,7 @@ mod test {
     #[test]
     fn test_sign_config_to_external_sign_config_fails() {
         let sign_config = SignConfig::default();
-        assert!(CallbackSignerConfig::new(&sign_config, 1024).is_err());
+        assert!(CallbackSignerConfig::new(&sign_config, Some(1024), None).is_err());
     }
 
     #[test]
@@ -253,7 +853,7 @@ mod test {
             ..Default::default()
         };
 
-        let result = CallbackSignerConfig::new(&sign_config, 1024);
+        let result = CallbackSignerConfig::new(&sign_config, Some(1024), None);
         let error = result.err().unwrap();
         assert_eq!(format!("{error}"), "Invalid signing algorithm provided")
     }
@@ -266,7 +866,7 @@ mod test {
             ..Default::default()
         };
 
-        let result = CallbackSignerConfig::new(&sign_config, 1024);
+        let result = CallbackSignerConfig::new(&sign_config, Some(1024), None);
         let error = result.err().unwrap();
         assert_eq!(
             format!("{error}"),
@@ -274,6 +874,70 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_reserve_size_computed_from_cert_chain() {
+        let mut sign_cert_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sign_cert_path.push("sample/es256_certs.pem");
+        let sign_config = SignConfig {
+            alg: Some(SigningAlg::Es256.to_string()),
+            sign_cert: Some(sign_cert_path.clone()),
+            ..Default::default()
+        };
+        let config = CallbackSignerConfig::new(&sign_config, None, None).unwrap();
+        assert!(config.auto_reserve_size);
+
+        let mut mock_callback_signer = MockSignCallback::default();
+        mock_callback_signer
+            .expect_sign()
+            .returning(|data| Ok(sample_sign("es256_private.key", SigningAlg::Es256, data)));
+        let signer = CallbackSigner::new(Box::new(mock_callback_signer), config);
+
+        let certs_size: usize = read_cert_chain(&sign_cert_path)
+            .unwrap()
+            .iter()
+            .map(|c| c.len())
+            .sum();
+        assert_eq!(signer.reserve_size(), SIGNATURE_OVERHEAD + certs_size);
+
+        // the computed size is enough to sign an asset
+        let tempdir = tempfile::tempdir().unwrap();
+        let output = tempdir.path().join("signed.jpg");
+        let mut builder = Builder::from_json(r#"{"title": "test"}"#).unwrap();
+        builder.base_path = Some(tempdir.path().to_path_buf());
+        let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/C.jpg");
+        builder.sign_file(&signer, &source, &output).unwrap();
+    }
+
+    #[test]
+    fn test_retry_reserve_size_grows_computed_size() {
+        let sign_config = SignConfig {
+            alg: Some(SigningAlg::Es256.to_string()),
+            sign_cert: Some(PathBuf::from("sample/es256_certs.pem")),
+            ..Default::default()
+        };
+        let config = CallbackSignerConfig::new(&sign_config, None, None).unwrap();
+        config.set_reserve_size(1000);
+        let signer = CallbackSigner::new(Box::<MockSignCallback>::default(), config);
+
+        let mut attempts = 0;
+        let result = signer.retry_reserve_size(|| {
+            attempts += 1;
+            match attempts {
+                1 => Err(Error::CoseSigboxTooSmall.into()),
+                _ => Ok(signer.config.reserve_size()),
+            }
+        });
+        assert_eq!(result.unwrap(), 2000);
+
+        // a reserve size given by the user is not changed
+        let config = CallbackSignerConfig::new(&sign_config, Some(1000), None).unwrap();
+        let signer = CallbackSigner::new(Box::<MockSignCallback>::default(), config);
+        let result: anyhow::Result<()> =
+            signer.retry_reserve_size(|| Err(Error::CoseSigboxTooSmall.into()));
+        assert!(result.is_err());
+        assert_eq!(signer.reserve_size(), 1000);
+    }
+
     #[test]
     fn test_try_from_succeeds_for_valid_sign_config() {
         let mut sign_cert_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -287,7 +951,8 @@ mod test {
         };
 
         let expected_reserve_size = 10248;
-        let esc = CallbackSignerConfig::new(&sign_config, expected_reserve_size).unwrap();
+        let esc =
+            CallbackSignerConfig::new(&sign_config, Some(expected_reserve_size), None).unwrap();
         let callback = Box::<MockSignCallback>::default();
         let signer = CallbackSigner::new(callback, esc);
 
@@ -306,11 +971,8 @@ mod test {
             ..Default::default()
         };
 
-        let config = CallbackSignerConfig::new(&sign_config, 10248).unwrap();
-        let callback = Box::<MockSignCallback>::default();
-        let signer = CallbackSigner::new(callback, config);
-
-        assert!(matches!(signer.certs(), Err(Error::FileNotFound(_))));
+        let error = CallbackSignerConfig::new(&sign_config, Some(10248), None).unwrap_err();
+        assert!(error.to_string().starts_with("Reading sign cert"));
     }
 
     #[test]
@@ -324,11 +986,8 @@ mod test {
             ..Default::default()
         };
 
-        let config = CallbackSignerConfig::new(&sign_config, 1024).unwrap();
-        let callback = Box::<MockSignCallback>::default();
-        let signer = CallbackSigner::new(callback, config);
-
-        assert!(matches!(signer.certs(), Err(Error::CoseInvalidCert)));
+        let error = CallbackSignerConfig::new(&sign_config, Some(1024), None).unwrap_err();
+        assert!(error.to_string().contains("contains no PEM certificates"));
     }
 
     #[test]
@@ -342,10 +1001,233 @@ mod test {
             ..Default::default()
         };
 
-        let config = CallbackSignerConfig::new(&sign_config, 1024).unwrap();
+        let config = CallbackSignerConfig::new(&sign_config, Some(1024), None).unwrap();
         let callback = Box::<MockSignCallback>::default();
         let signer = CallbackSigner::new(callback, config);
 
         assert_eq!(signer.certs().unwrap().len(), 2);
     }
+
+    // writes an executable shell script
+    #[cfg(unix)]
+    fn script(dir: &Path, name: &str, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let script = dir.join(name);
+        std::fs::write(&script, format!("#!/bin/sh\n{body}\n")).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        script
+    }
+
+    // writes a signer script that saves its stdin to request.json and replies with `response`
+    #[cfg(unix)]
+    fn script_signer(dir: &Path, response: &str) -> PathBuf {
+        let response_path = dir.join("response.json");
+        std::fs::write(&response_path, response).unwrap();
+        let body = format!(
+            "cat > '{}'\ncat '{}'",
+            dir.join("request.json").display(),
+            response_path.display()
+        );
+        script(dir, "signer.sh", &body)
+    }
+
+    fn es256_config() -> CallbackSignerConfig {
+        let mut sign_cert_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        sign_cert_path.push("sample/es256_certs.pem");
+
+        let sign_config = SignConfig {
+            alg: Some(SigningAlg::Es256.to_string()),
+            sign_cert: Some(sign_cert_path),
+            ..Default::default()
+        };
+        CallbackSignerConfig::new(&sign_config, Some(1024), None).unwrap()
+    }
+
+    // a COSE Sig_structure over a claim of the manifest with `label`
+    fn sig_structure(label: &str) -> Vec<u8> {
+        let claim = ciborium::Value::Map(vec![(
+            ciborium::Value::Text("signature".to_owned()),
+            ciborium::Value::Text(format!("self#jumbf=/c2pa/{label}/c2pa.signature")),
+        )]);
+        let mut payload = Vec::new();
+        ciborium::into_writer(&claim, &mut payload).unwrap();
+        let structure = ciborium::Value::Array(vec![
+            ciborium::Value::Text("Signature1".to_owned()),
+            ciborium::Value::Bytes(Vec::new()),
+            ciborium::Value::Bytes(Vec::new()),
+            ciborium::Value::Bytes(payload),
+        ]);
+        let mut data = Vec::new();
+        ciborium::into_writer(&structure, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_json_protocol_request_and_response() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let script = script_signer(tempdir.path(), r#"{"signature": "AQID"}"#);
+        let context = SignContext {
+            asset_path: Some(PathBuf::from("image.jpg")),
+        };
+        let runner = ExternalProcessRunner::new(es256_config(), script)
+            .with_protocol(SignerProtocol::Json, context);
+
+        let data = sig_structure("urn:uuid:1234");
+        assert_eq!(runner.sign(&data).unwrap(), vec![1, 2, 3]);
+
+        let request: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(tempdir.path().join("request.json")).unwrap())
+                .unwrap();
+        assert_eq!(request["data"], BASE64.encode(&data));
+        assert_eq!(request["alg"], "es256");
+        assert_eq!(request["asset_path"], "image.jpg");
+        assert_eq!(request["manifest_label"], "urn:uuid:1234");
+        assert_eq!(request["reserve_size"], 1024);
+
+        // bytes that aren't a claim have no label
+        runner.sign(b"claim").unwrap();
+        let request: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(tempdir.path().join("request.json")).unwrap())
+                .unwrap();
+        assert!(request.get("manifest_label").is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_json_protocol_rejects_invalid_response() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let runner =
+            ExternalProcessRunner::new(es256_config(), script_signer(tempdir.path(), "AQID"))
+                .with_protocol(SignerProtocol::Json, SignContext::default());
+        let error = runner.sign(b"claim").unwrap_err();
+        assert!(error.to_string().contains("valid JSON response"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_json_protocol_checks_returned_certs() {
+        let config = es256_config();
+        let chain = std::fs::read_to_string(&config.sign_cert_path).unwrap();
+        // only the signing certificate, without the rest of the chain
+        let end = "-----END CERTIFICATE-----";
+        let leaf = &chain[..chain.find(end).unwrap() + end.len()];
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let response = serde_json::json!({ "signature": "AQID", "certs": chain }).to_string();
+        let runner =
+            ExternalProcessRunner::new(config.clone(), script_signer(tempdir.path(), &response))
+                .with_protocol(SignerProtocol::Json, SignContext::default());
+        assert_eq!(runner.sign(b"claim").unwrap(), vec![1, 2, 3]);
+
+        let response = serde_json::json!({ "signature": "AQID", "certs": leaf }).to_string();
+        let runner = ExternalProcessRunner::new(config, script_signer(tempdir.path(), &response))
+            .with_protocol(SignerProtocol::Json, SignContext::default());
+        let error = runner.sign(b"claim").unwrap_err();
+        assert!(error.to_string().contains("differs from the sign_cert"));
+    }
+
+    #[test]
+    fn test_manifest_label_of_each_signing() {
+        let labels = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let signed = labels.clone();
+        let mut callback = MockSignCallback::default();
+        callback.expect_sign().returning(move |data| {
+            signed.lock().unwrap().extend(manifest_label(data));
+            Ok(sample_sign("es256_private.key", SigningAlg::Es256, data))
+        });
+        let sign_config = SignConfig {
+            alg: Some(SigningAlg::Es256.to_string()),
+            sign_cert: Some(PathBuf::from("sample/es256_certs.pem")),
+            ..Default::default()
+        };
+        let config = CallbackSignerConfig::new(&sign_config, None, None).unwrap();
+        let signer = CallbackSigner::new(Box::new(callback), config);
+
+        // a builder signs each asset with a new manifest label
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut builder = Builder::from_json(r#"{"title": "test"}"#).unwrap();
+        let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/C.jpg");
+        let mut active = Vec::new();
+        for name in ["one.jpg", "two.jpg"] {
+            let output = tempdir.path().join(name);
+            builder.sign_file(&signer, &source, &output).unwrap();
+            let reader = c2pa::Reader::from_file(&output).unwrap();
+            active.push(reader.active_label().unwrap().to_owned());
+        }
+        assert_eq!(*labels.lock().unwrap(), active);
+        assert_ne!(active[0], active[1]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_process_timeout() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let signer = script(tempdir.path(), "slow.sh", "sleep 10");
+        let runner = ExternalProcessRunner::new(es256_config(), signer).with_process_args(
+            SignerProcessArgs {
+                timeout: Some(1),
+                ..Default::default()
+            },
+        );
+
+        let started = std::time::Instant::now();
+        let error = runner.sign(b"claim").unwrap_err();
+        assert!(error.to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_process_retries_transient_failure() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let marker = tempdir.path().join("failed_once");
+        let body = format!(
+            "cat > /dev/null\nif [ -f '{0}' ]; then printf sig; else touch '{0}'; exit 75; fi",
+            marker.display()
+        );
+        let signer = script(tempdir.path(), "flaky.sh", &body);
+        let retries = SignerProcessArgs {
+            retries: 2,
+            retry_delay: 1,
+            ..Default::default()
+        };
+
+        let runner = ExternalProcessRunner::new(es256_config(), signer.clone());
+        assert!(runner.sign(b"claim").is_err());
+
+        std::fs::remove_file(&marker).unwrap();
+        let runner =
+            ExternalProcessRunner::new(es256_config(), signer).with_process_args(retries.clone());
+        assert_eq!(runner.sign(b"claim").unwrap(), b"sig");
+
+        // other failures are reported without running the process again
+        let runs = tempdir.path().join("runs");
+        let body = format!("cat > /dev/null\necho run >> '{}'\nexit 1", runs.display());
+        let signer = script(tempdir.path(), "failing.sh", &body);
+        let runner = ExternalProcessRunner::new(es256_config(), signer).with_process_args(retries);
+        assert!(runner.sign(b"claim").is_err());
+        assert_eq!(std::fs::read_to_string(&runs).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_process_extra_args_and_env() {
+        let tempdir = tempfile::tempdir().unwrap();
+        // the first six arguments are --reserve-size, --alg and --sign-cert with their values
+        let signer = script(
+            tempdir.path(),
+            "echo.sh",
+            "cat > /dev/null\nprintf '%s %s' \"$7\" \"$SIGNER_KEY_ID\"",
+        );
+        let runner = ExternalProcessRunner::new(es256_config(), signer).with_process_args(
+            SignerProcessArgs {
+                extra_args: vec!["--profile=prod".to_owned()],
+                env: vec![parse_env_var("SIGNER_KEY_ID=key-1").unwrap()],
+                ..Default::default()
+            },
+        );
+        assert_eq!(runner.sign(b"claim").unwrap(), b"--profile=prod key-1");
+    }
 }
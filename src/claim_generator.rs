@@ -0,0 +1,171 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+use std::{fs::File, path::PathBuf};
+
+use anyhow::{Context, Result};
+use c2pa::{Builder, ClaimGeneratorInfo, ResourceRef};
+use clap::Args;
+
+/// Command line settings for the application creating the manifest, recorded in the
+/// claim generator info ahead of c2patool.
+#[derive(Args, Debug, Default)]
+pub struct ClaimGeneratorArgs {
+    /// Name of the application creating the manifest, recorded as its claim generator.
+    #[clap(long)]
+    pub app_name: Option<String>,
+
+    /// Version of the app_name application.
+    #[clap(long, requires = "app_name")]
+    pub app_version: Option<String>,
+
+    /// Path to an icon image of the app_name application.
+    #[clap(long, requires = "app_name")]
+    pub app_icon: Option<PathBuf>,
+}
+
+impl ClaimGeneratorArgs {
+    pub fn is_empty(&self) -> bool {
+        self.app_name.is_none() && self.app_version.is_none() && self.app_icon.is_none()
+    }
+
+    // the application given on the command line, with its icon added to the builder's resources
+    fn app(&self, builder: &mut Builder) -> Result<Option<ClaimGeneratorInfo>> {
+        let Some(name) = &self.app_name else {
+            return Ok(None);
+        };
+        let mut app = ClaimGeneratorInfo::new(name);
+        if let Some(version) = &self.app_version {
+            app.set_version(version);
+        }
+        if let Some(icon) = &self.app_icon {
+            let format = c2pa::format_from_path(icon)
+                .context(format!("App icon {:?} has no file extension", icon))?;
+            let id = icon
+                .file_name()
+                .context(format!("Invalid app icon path {:?}", icon))?
+                .to_string_lossy()
+                .into_owned();
+            let file = File::open(icon).context(format!("Reading app icon {:?}", icon))?;
+            builder.add_resource(&id, file)?;
+            app.set_icon(ResourceRef::new(format, id));
+        }
+        Ok(Some(app))
+    }
+
+    /// Sets the builder's claim generator info to the application given on the command line,
+    /// then those of the manifest definition, then c2patool.
+    ///
+    /// The c2pa-rs entry the SDK uses when the definition has none is dropped, since the SDK
+    /// records its own version with the first entry anyway.
+    pub fn merge_into(&self, builder: &mut Builder) -> Result<()> {
+        let app = self.app(builder)?;
+        let defined = std::mem::take(&mut builder.definition.claim_generator_info);
+
+        let mut info: Vec<ClaimGeneratorInfo> = app.into_iter().collect();
+        for entry in defined {
+            let duplicate = info.iter().any(|i| i.name == entry.name);
+            if !duplicate && entry.name != c2pa::NAME && entry.name != env!("CARGO_PKG_NAME") {
+                info.push(entry);
+            }
+        }
+        let mut tool = ClaimGeneratorInfo::new(env!("CARGO_PKG_NAME"));
+        tool.set_version(env!("CARGO_PKG_VERSION"));
+        info.push(tool);
+
+        builder.definition.claim_generator_info = info;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    fn names(builder: &Builder) -> Vec<(&str, Option<&str>)> {
+        builder
+            .definition
+            .claim_generator_info
+            .iter()
+            .map(|i| (i.name.as_str(), i.version.as_deref()))
+            .collect()
+    }
+
+    const TOOL: (&str, Option<&str>) = (env!("CARGO_PKG_NAME"), Some(env!("CARGO_PKG_VERSION")));
+
+    #[test]
+    fn test_default_definition() {
+        // without claim_generator_info, the SDK defaults to a c2pa-rs entry
+        let mut builder = Builder::from_json("{}").unwrap();
+        ClaimGeneratorArgs::default()
+            .merge_into(&mut builder)
+            .unwrap();
+        assert_eq!(names(&builder), vec![TOOL]);
+
+        let mut builder = Builder::from_json(r#"{"claim_generator_info": []}"#).unwrap();
+        ClaimGeneratorArgs::default()
+            .merge_into(&mut builder)
+            .unwrap();
+        assert_eq!(names(&builder), vec![TOOL]);
+    }
+
+    #[test]
+    fn test_defined_generators_come_first() {
+        let json = r#"{"claim_generator_info": [
+            {"name": "MyApp", "version": "2.0"},
+            {"name": "c2patool", "version": "0.1"}
+        ]}"#;
+        let mut builder = Builder::from_json(json).unwrap();
+        ClaimGeneratorArgs::default()
+            .merge_into(&mut builder)
+            .unwrap();
+        assert_eq!(names(&builder), vec![("MyApp", Some("2.0")), TOOL]);
+    }
+
+    #[test]
+    fn test_app_from_command_line() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let icon = tempdir.path().join("icon.svg");
+        std::fs::write(&icon, "<svg/>").unwrap();
+
+        let json = r#"{"claim_generator_info": [
+            {"name": "MyApp", "version": "2.0"},
+            {"name": "Plugin"}
+        ]}"#;
+        let mut builder = Builder::from_json(json).unwrap();
+        let args = ClaimGeneratorArgs {
+            app_name: Some("MyApp".to_owned()),
+            app_version: Some("3.0".to_owned()),
+            app_icon: Some(icon),
+        };
+        args.merge_into(&mut builder).unwrap();
+        assert_eq!(
+            names(&builder),
+            vec![("MyApp", Some("3.0")), ("Plugin", None), TOOL]
+        );
+
+        let icon = builder.definition.claim_generator_info[0].icon().unwrap();
+        assert_eq!(
+            serde_json::to_value(icon).unwrap()["format"],
+            "image/svg+xml"
+        );
+
+        let args = ClaimGeneratorArgs {
+            app_icon: Some(tempdir.path().join("missing.png")),
+            ..args
+        };
+        let error = args.merge_into(&mut builder).unwrap_err();
+        assert!(error.to_string().contains("Reading app icon"));
+    }
+}
@@ -0,0 +1,373 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+//! Extracts a single box from a manifest store by JUMBF URI or assertion label, such as an
+//! assertion's data, a thumbnail, a claim or signature, a whole manifest or the raw store.
+//!
+//! The store is read without validating it, so the content of damaged or tampered manifests
+//! can be examined too.
+
+use std::{
+    fs::File,
+    io::{IsTerminal, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{remote::RemoteResolver, sidecar};
+
+/// A JUMBF box: its type and its payload, or for superboxes its label and child boxes.
+struct JumbfBox<'a> {
+    box_type: [u8; 4],
+    // the whole box, header included
+    data: &'a [u8],
+    payload: &'a [u8],
+}
+
+impl<'a> JumbfBox<'a> {
+    fn is_superbox(&self) -> bool {
+        &self.box_type == b"jumb"
+    }
+
+    // the boxes in the payload of a superbox
+    fn children(&self) -> Result<Vec<JumbfBox<'a>>> {
+        let mut children = Vec::new();
+        let mut rest = self.payload;
+        while !rest.is_empty() {
+            let (child, next) = parse_box(rest)?;
+            children.push(child);
+            rest = next;
+        }
+        Ok(children)
+    }
+
+    // the label in a superbox's description box
+    fn label(&self) -> Option<String> {
+        let description = self.children().ok()?.into_iter().next()?;
+        if &description.box_type != b"jumd" || description.payload.len() < 17 {
+            return None;
+        }
+        // a 16 byte content type UUID, then toggles telling which fields follow
+        let toggles = description.payload[16];
+        if toggles & 0x02 == 0 {
+            return None;
+        }
+        let label = &description.payload[17..];
+        let end = label.iter().position(|&b| b == 0).unwrap_or(label.len());
+        Some(String::from_utf8_lossy(&label[..end]).into_owned())
+    }
+
+    // the child superbox with a label
+    fn child(&self, label: &str) -> Result<Option<JumbfBox<'a>>> {
+        for child in self.children()? {
+            if child.is_superbox() && child.label().as_deref() == Some(label) {
+                return Ok(Some(child));
+            }
+        }
+        Ok(None)
+    }
+
+    // the labels of the child superboxes
+    fn child_labels(&self) -> Vec<String> {
+        self.children()
+            .unwrap_or_default()
+            .iter()
+            .filter(|c| c.is_superbox())
+            .filter_map(|c| c.label())
+            .collect()
+    }
+
+    /// The content of a superbox: the data of an assertion, claim, signature or embedded file,
+    /// or the whole box when it holds other superboxes, like a manifest or the store.
+    fn content(&self) -> Result<&'a [u8]> {
+        let children = self.children()?;
+        let content: Vec<&JumbfBox> = children.iter().skip(1).collect();
+        if content.iter().any(|c| c.is_superbox()) {
+            return Ok(self.data);
+        }
+        // an embedded file has a description box, then the file's bytes
+        if let Some(file) = content.iter().find(|c| &c.box_type == b"bidb") {
+            return Ok(file.payload);
+        }
+        match content.as_slice() {
+            // binary data follows the UUID of its type
+            [uuid] if &uuid.box_type == b"uuid" && uuid.payload.len() >= 16 => {
+                Ok(&uuid.payload[16..])
+            }
+            [single] => Ok(single.payload),
+            _ => Ok(self.data),
+        }
+    }
+}
+
+// parses the box at the start of `data`, returning it and the data that follows it
+fn parse_box(data: &[u8]) -> Result<(JumbfBox<'_>, &[u8])> {
+    if data.len() < 8 {
+        bail!("Truncated JUMBF box");
+    }
+    let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as u64;
+    let box_type = [data[4], data[5], data[6], data[7]];
+    let (size, header) = match size {
+        // the box extends to the end of the data
+        0 => (data.len() as u64, 8),
+        // the size follows the type as 64 bits
+        1 => {
+            if data.len() < 16 {
+                bail!("Truncated JUMBF box");
+            }
+            let mut large = [0u8; 8];
+            large.copy_from_slice(&data[8..16]);
+            (u64::from_be_bytes(large), 16)
+        }
+        size => (size, 8),
+    };
+    if size < header as u64 || size > data.len() as u64 {
+        bail!(
+            "Invalid size {} of JUMBF box {:?}",
+            size,
+            String::from_utf8_lossy(&box_type)
+        );
+    }
+    let size = size as usize;
+    Ok((
+        JumbfBox {
+            box_type,
+            data: &data[..size],
+            payload: &data[header..size],
+        },
+        &data[size..],
+    ))
+}
+
+// the path of labels from the store to the box a URI or label refers to
+fn resolve(store: &JumbfBox, uri: &str) -> Result<Vec<String>> {
+    let (uri, is_uri) = match uri.strip_prefix("self#jumbf=") {
+        Some(uri) => (uri, true),
+        None => (uri, uri.contains('/')),
+    };
+    if uri == "/c2pa" || uri == "/c2pa/" {
+        return Ok(Vec::new());
+    }
+    if let Some(path) = uri.strip_prefix("/c2pa/") {
+        return Ok(path.split('/').map(str::to_owned).collect());
+    }
+    // relative URIs and assertion labels are in the active manifest, the last in the store
+    let active = store
+        .children()?
+        .iter()
+        .rev()
+        .find(|c| c.is_superbox())
+        .and_then(|c| c.label())
+        .context("The manifest store has no manifests")?;
+    let mut path = vec![active];
+    if is_uri {
+        path.extend(uri.split('/').map(str::to_owned));
+    } else {
+        path.extend(["c2pa.assertions".to_owned(), uri.to_owned()]);
+    }
+    Ok(path)
+}
+
+/// Finds the content of the box that `uri` refers to in a manifest store.
+///
+/// `uri` is a JUMBF URI like `self#jumbf=/c2pa/<manifest>/c2pa.assertions/c2pa.actions`,
+/// a URI relative to the active manifest like `self#jumbf=c2pa.signature`, an assertion
+/// label of the active manifest like `c2pa.actions`, or `self#jumbf=/c2pa` for the store.
+pub fn find<'a>(store_data: &'a [u8], uri: &str) -> Result<&'a [u8]> {
+    find_box(store_data, uri)?.content()
+}
+
+/// The data of an assertion in a manifest store as it is stored, if it is stored as CBOR.
+pub fn stored_cbor<'a>(
+    store_data: &'a [u8],
+    manifest: &str,
+    assertion: &str,
+) -> Result<Option<&'a [u8]>> {
+    let uri = format!("/c2pa/{}/c2pa.assertions/{}", manifest, assertion);
+    let children = find_box(store_data, &uri)?.children()?;
+    Ok(match children.as_slice() {
+        [_, cbor] if &cbor.box_type == b"cbor" => Some(cbor.payload),
+        _ => None,
+    })
+}
+
+// the superbox that `uri` refers to in a manifest store
+fn find_box<'a>(store_data: &'a [u8], uri: &str) -> Result<JumbfBox<'a>> {
+    let (store, _) = parse_box(store_data)?;
+    if !store.is_superbox() {
+        bail!("The manifest store is not a JUMBF superbox");
+    }
+    let mut current = store;
+    let mut location = "/c2pa".to_owned();
+    for label in resolve(&current, uri)? {
+        current = match current.child(&label)? {
+            Some(child) => child,
+            None => bail!(
+                "No box labeled {:?} in {}. It has: {}",
+                label,
+                location,
+                current.child_labels().join(", ")
+            ),
+        };
+        location = format!("{}/{}", location, label);
+    }
+    Ok(current)
+}
+
+/// Extracts the box that `uri` refers to from the manifest store of the asset at `path`, to
+/// the `output` file or to stdout.
+pub fn extract(
+    path: &Path,
+    uri: &str,
+    output: Option<&Path>,
+    force: bool,
+    manifest_file: Option<&Path>,
+    remote: &RemoteResolver,
+) -> Result<()> {
+    let (store, _) = sidecar::locate(path, manifest_file, remote)?;
+    let content = find(&store, uri)?;
+    match output {
+        Some(output) => {
+            if output.exists() && !force {
+                bail!("Output already exists, use -f/force to force write");
+            }
+            File::create(output)
+                .and_then(|mut file| file.write_all(content))
+                .context(format!("Writing {:?}", output))?;
+            eprintln!("Wrote {} bytes to {:?}", content.len(), output);
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            if stdout.is_terminal() && std::str::from_utf8(content).is_err() {
+                bail!("The content is binary. Use -o to write it to a file, or redirect it");
+            }
+            stdout.write_all(content)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    fn jbox(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn superbox(label: &str, children: &[Vec<u8>]) -> Vec<u8> {
+        let mut description = vec![0u8; 16];
+        description.push(0x03);
+        description.extend_from_slice(label.as_bytes());
+        description.push(0);
+        let mut payload = jbox(b"jumd", &description);
+        for child in children {
+            payload.extend_from_slice(child);
+        }
+        jbox(b"jumb", &payload)
+    }
+
+    fn manifest(label: &str, actions: &[u8]) -> Vec<u8> {
+        superbox(
+            label,
+            &[
+                superbox(
+                    "c2pa.assertions",
+                    &[
+                        superbox("c2pa.actions", &[jbox(b"cbor", actions)]),
+                        superbox(
+                            "c2pa.thumbnail.claim.jpeg",
+                            &[jbox(b"bfdb", b"\0image/jpeg\0"), jbox(b"bidb", b"JPEG")],
+                        ),
+                        superbox(
+                            "com.example.binary",
+                            &[jbox(b"uuid", &[[7u8; 16].as_slice(), b"bin"].concat())],
+                        ),
+                    ],
+                ),
+                superbox("c2pa.signature", &[jbox(b"cbor", b"sig")]),
+            ],
+        )
+    }
+
+    fn store() -> Vec<u8> {
+        superbox(
+            "c2pa",
+            &[
+                manifest("urn:uuid:1", b"first"),
+                manifest("urn:uuid:2", b"second"),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_find() {
+        let store = store();
+        assert_eq!(find(&store, "c2pa.actions").unwrap(), b"second");
+        assert_eq!(
+            find(
+                &store,
+                "self#jumbf=/c2pa/urn:uuid:1/c2pa.assertions/c2pa.actions"
+            )
+            .unwrap(),
+            b"first"
+        );
+        assert_eq!(find(&store, "self#jumbf=c2pa.signature").unwrap(), b"sig");
+        assert_eq!(
+            find(
+                &store,
+                "self#jumbf=c2pa.assertions/c2pa.thumbnail.claim.jpeg"
+            )
+            .unwrap(),
+            b"JPEG"
+        );
+        assert_eq!(find(&store, "com.example.binary").unwrap(), b"bin");
+        assert_eq!(find(&store, "self#jumbf=/c2pa").unwrap(), store.as_slice());
+        assert_eq!(
+            find(&store, "/c2pa/urn:uuid:1").unwrap(),
+            manifest("urn:uuid:1", b"first").as_slice()
+        );
+    }
+
+    #[test]
+    fn test_stored_cbor() {
+        let store = store();
+        assert_eq!(
+            stored_cbor(&store, "urn:uuid:1", "c2pa.actions").unwrap(),
+            Some(b"first".as_slice())
+        );
+        assert_eq!(
+            stored_cbor(&store, "urn:uuid:2", "c2pa.thumbnail.claim.jpeg").unwrap(),
+            None
+        );
+        assert!(stored_cbor(&store, "urn:uuid:3", "c2pa.actions").is_err());
+    }
+
+    #[test]
+    fn test_missing_box() {
+        let error = find(&store(), "c2pa.hash.data").unwrap_err().to_string();
+        assert!(
+            error.contains("No box labeled \"c2pa.hash.data\" in /c2pa/urn:uuid:2/c2pa.assertions")
+        );
+        assert!(error.contains("It has: c2pa.actions, c2pa.thumbnail.claim.jpeg"));
+
+        let mut truncated = store();
+        truncated.truncate(40);
+        assert!(find(&truncated, "c2pa.actions").is_err());
+    }
+}
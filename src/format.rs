@@ -0,0 +1,234 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+//! Writes manifest, detailed and ingredient reports as JSON, canonical JSON, YAML or CBOR.
+
+use std::io::{IsTerminal, Write};
+
+use anyhow::{bail, Context, Result};
+use c2pa::{Ingredient, Reader};
+use ciborium::Value as CborValue;
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+/// The format reports are written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// Pretty printed JSON, in the order the SDK reports it.
+    #[default]
+    Json,
+    /// JSON with the keys of every object sorted, for stable diffs.
+    CanonicalJson,
+    /// YAML.
+    Yaml,
+    /// Binary CBOR, with the assertions of manifest reports as they are stored.
+    Cbor,
+}
+
+impl ReportFormat {
+    /// The file extension for reports in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Json | ReportFormat::CanonicalJson => "json",
+            ReportFormat::Yaml => "yaml",
+            ReportFormat::Cbor => "cbor",
+        }
+    }
+
+    /// Encodes a report in this format.
+    pub fn encode(self, report: &Value) -> Result<Vec<u8>> {
+        Ok(match self {
+            ReportFormat::Json => serde_json::to_vec_pretty(report)?,
+            ReportFormat::CanonicalJson => serde_json::to_vec_pretty(&sorted(report))?,
+            ReportFormat::Yaml => serde_yaml::to_string(report)?.into_bytes(),
+            ReportFormat::Cbor => {
+                let mut cbor = Vec::new();
+                ciborium::into_writer(report, &mut cbor).context("Encoding CBOR")?;
+                cbor
+            }
+        })
+    }
+
+    /// Encodes a manifest store report in this format.
+    ///
+    /// In CBOR, the data of each assertion that is stored as CBOR is the assertion's bytes as
+    /// stored in `store`, as an encoded CBOR data item (tag 24), instead of the JSON the SDK
+    /// converts it to.
+    pub fn encode_store(self, report: &Value, store: &[u8]) -> Result<Vec<u8>> {
+        if self != ReportFormat::Cbor {
+            return self.encode(report);
+        }
+        let mut cbor_report = CborValue::serialized(report).context("Encoding CBOR")?;
+        let manifests = report["manifests"].as_object().into_iter().flatten();
+        for (label, manifest) in manifests {
+            let assertions = manifest["assertions"].as_array().into_iter().flatten();
+            for (index, assertion) in assertions.enumerate() {
+                let mut name = assertion["label"].as_str().unwrap_or_default().to_owned();
+                // the store labels the second instance of an assertion `__1`, and so on
+                if let Some(instance) = assertion["instance"].as_u64().filter(|&i| i > 0) {
+                    name = format!("{}__{}", name, instance);
+                }
+                let Some(stored) = crate::extract::stored_cbor(store, label, &name)? else {
+                    continue;
+                };
+                let data = member(&mut cbor_report, "manifests")
+                    .and_then(|manifests| member(manifests, label))
+                    .and_then(|manifest| member(manifest, "assertions"))
+                    .and_then(|assertions| assertions.as_array_mut()?.get_mut(index))
+                    .and_then(|assertion| member(assertion, "data"));
+                if let Some(data) = data {
+                    *data = CborValue::Tag(24, Box::new(CborValue::Bytes(stored.to_vec())));
+                }
+            }
+        }
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&cbor_report, &mut cbor).context("Encoding CBOR")?;
+        Ok(cbor)
+    }
+
+    /// Writes a report to stdout in this format.
+    pub fn print(self, report: &Value) -> Result<()> {
+        self.write(&self.encode(report)?)
+    }
+
+    /// Writes a manifest store report to stdout in this format, with the assertions of CBOR
+    /// reports as they are stored in `store`.
+    pub fn print_store(self, report: &Value, store: &[u8]) -> Result<()> {
+        self.write(&self.encode_store(report, store)?)
+    }
+
+    // writes an encoded report to stdout
+    fn write(self, report: &[u8]) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        if self == ReportFormat::Cbor && stdout.is_terminal() {
+            bail!("CBOR reports are binary. Redirect them to a file or another program");
+        }
+        stdout.write_all(report)?;
+        if self != ReportFormat::Cbor {
+            writeln!(stdout)?;
+        }
+        Ok(())
+    }
+}
+
+/// The manifest store report of a reader, or its detailed report with the claims, assertion
+/// stores and signatures of each manifest.
+///
+/// The SDK has no API for the detailed report of a reader other than its `Debug` output, which
+/// is the JSON of its `ManifestStoreReport`. `test_detailed_report` fails if that changes.
+pub fn reader_report(reader: &Reader, detailed: bool) -> Result<Value> {
+    let json = match detailed {
+        true => format!("{:?}", reader),
+        false => reader.json(),
+    };
+    serde_json::from_str(&json).context("The SDK returned a report that is not valid JSON")
+}
+
+/// The report of an ingredient.
+pub fn ingredient_report(ingredient: &Ingredient) -> Result<Value> {
+    Ok(serde_json::to_value(ingredient)?)
+}
+
+// the member of a CBOR map with a text key
+fn member<'a>(value: &'a mut CborValue, key: &str) -> Option<&'a mut CborValue> {
+    let map = value.as_map_mut()?;
+    map.iter_mut()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+}
+
+// copies a value with the keys of every object in sorted order
+fn sorted(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let sorted_map: Map<String, Value> = keys
+                .into_iter()
+                .map(|k| (k.clone(), sorted(&map[k])))
+                .collect();
+            Value::Object(sorted_map)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(sorted).collect()),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn report() -> Value {
+        json!({
+            "title": "Image: \"one\"",
+            "format": "image/jpeg",
+            "assertions": [
+                {"label": "c2pa.actions", "data": {"actions": [{"action": "c2pa.created"}]}},
+                {"label": "stds.schema-org.CreativeWork", "data": {}}
+            ],
+            "count": 2,
+            "valid": true,
+            "note": null,
+            "tags": ["yes", "2", ""],
+            "empty": []
+        })
+    }
+
+    #[test]
+    fn test_canonical_json_sorts_keys() {
+        let canonical = ReportFormat::CanonicalJson.encode(&report()).unwrap();
+        let text = String::from_utf8(canonical).unwrap();
+        let keys: Vec<usize> = ["assertions", "count", "empty", "format", "note", "tags"]
+            .iter()
+            .map(|k| text.find(&format!("\"{}\"", k)).unwrap())
+            .collect();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        // nested objects are sorted too
+        assert!(text.find("\"data\"").unwrap() < text.find("\"label\"").unwrap());
+        assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), report());
+    }
+
+    #[test]
+    fn test_yaml() {
+        let yaml = String::from_utf8(ReportFormat::Yaml.encode(&report()).unwrap()).unwrap();
+        assert!(yaml.contains("title: 'Image: \"one\"'\n"));
+        // strings that would read back as other types are quoted
+        assert!(yaml.contains("- '2'\n- ''\n"));
+        assert_eq!(serde_yaml::from_str::<Value>(&yaml).unwrap(), report());
+    }
+
+    #[test]
+    fn test_detailed_report() {
+        let reader = Reader::from_file(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/C.jpg"),
+        )
+        .unwrap();
+        let report = reader_report(&reader, true)
+            .expect("the Debug output of a Reader is no longer a JSON detailed report");
+        let active = report["active_manifest"].as_str().unwrap();
+        let manifest = &report["manifests"][active];
+        assert!(manifest["claim"].is_object());
+        assert!(manifest["assertion_store"].is_object());
+        assert!(manifest["signature"].is_object());
+    }
+
+    #[test]
+    fn test_cbor_round_trip() {
+        let cbor = ReportFormat::Cbor.encode(&report()).unwrap();
+        let decoded: Value = ciborium::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(decoded, report());
+    }
+}
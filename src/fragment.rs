@@ -0,0 +1,486 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    ffi::OsStr,
+    fs::{create_dir_all, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use c2pa::{Builder, Reader, Signer};
+
+use crate::{
+    audit::{AuditLog, Flow},
+    special_errs,
+};
+
+/// An init segment and the fragments that go with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendition {
+    pub init: PathBuf,
+    pub fragments: Vec<PathBuf>,
+    /// Folder the signed rendition is written to, relative to the output folder.
+    pub output_dir: PathBuf,
+}
+
+/// Finds the init segments matching `init_pattern` and the fragments matching
+/// `frag_pattern` in the folder of each init segment.
+///
+/// Fragments are sorted by name with runs of digits compared as numbers, so `seg-2.m4s`
+/// comes before `seg-10.m4s`. Each rendition's output folder is the init segment's folder
+/// relative to the glob root, the longest part of `init_pattern` without glob characters.
+/// Returns no renditions when nothing matches, leaving callers to decide if that is an error.
+pub fn find_renditions(init_pattern: &Path, frag_pattern: &Path) -> Result<Vec<Rendition>> {
+    let mut renditions = Vec::new();
+    let root = glob_root(init_pattern);
+
+    // search folders for init segments
+    let ip = init_pattern
+        .to_str()
+        .context("could not parse source pattern")?;
+    let inits = glob::glob(ip).context("could not process glob pattern")?;
+    for init in inits {
+        match init {
+            Ok(p) => {
+                let mut fragments = Vec::new();
+                let init_dir = p.parent().context("init segment had no parent dir")?;
+                let seg_glob = init_dir.join(frag_pattern); // segment match pattern
+
+                // grab the fragments that go with this init segment
+                let seg_glob_str = seg_glob.to_str().context("fragment path not valid")?;
+                let seg_paths = glob::glob(seg_glob_str).context("fragment glob not valid")?;
+                for seg in seg_paths {
+                    match seg {
+                        Ok(f) => fragments.push(f),
+                        Err(_) => return Err(anyhow!("fragment path not valid")),
+                    }
+                }
+                fragments.sort_by(|a, b| natural_cmp(a, b));
+
+                // keep the init segment's own folder even when the pattern names it literally
+                let base = if init_dir == root {
+                    init_dir.parent().unwrap_or(init_dir)
+                } else {
+                    &root
+                };
+                let output_dir = init_dir
+                    .strip_prefix(base)
+                    .context("init segment is outside the glob root")?
+                    .to_path_buf();
+                renditions.push(Rendition {
+                    init: p,
+                    fragments,
+                    output_dir,
+                });
+            }
+            Err(_) => bail!("bad path to init segment"),
+        }
+    }
+
+    Ok(renditions)
+}
+
+// returns the leading folders of a glob pattern that contain no glob characters
+fn glob_root(pattern: &Path) -> PathBuf {
+    let dir = pattern.parent().unwrap_or(Path::new(""));
+    dir.components()
+        .take_while(|c| {
+            !c.as_os_str()
+                .to_string_lossy()
+                .contains(['*', '?', '[', ']'])
+        })
+        .collect()
+}
+
+// compares file names, treating runs of digits as numbers
+fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    fn chunks(s: &str) -> Vec<(bool, &str)> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut chars = s.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let digit = c.is_ascii_digit();
+            if chars
+                .peek()
+                .is_none_or(|(_, n)| n.is_ascii_digit() != digit)
+            {
+                chunks.push((digit, &s[start..i + c.len_utf8()]));
+                start = i + c.len_utf8();
+            }
+        }
+        chunks
+    }
+
+    let a_name = a.file_name().unwrap_or_default().to_string_lossy();
+    let b_name = b.file_name().unwrap_or_default().to_string_lossy();
+    for (x, y) in chunks(&a_name).into_iter().zip(chunks(&b_name)) {
+        let ordering = match (x, y) {
+            ((true, x), (true, y)) => {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            ((_, x), (_, y)) => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a_name.cmp(&b_name).then_with(|| a.cmp(b))
+}
+
+/// Checks that no two files of the renditions would be written to the same output path.
+pub fn check_outputs(renditions: &[Rendition]) -> Result<()> {
+    let mut outputs: HashMap<PathBuf, &Path> = HashMap::new();
+    for rendition in renditions {
+        for file in std::iter::once(&rendition.init).chain(rendition.fragments.iter()) {
+            let name = file.file_name().context("invalid file name")?;
+            let output = rendition.output_dir.join(name);
+            if let Some(other) = outputs.insert(output.clone(), file) {
+                bail!(
+                    "{:?} and {:?} would both be written to {:?}",
+                    other,
+                    file,
+                    output
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds a manifest to each rendition, writing the signed files under `output_path`.
+///
+/// Each rendition is recorded in the `audit_log` as soon as it is signed.
+pub fn sign_renditions(
+    builder: &mut Builder,
+    signer: &dyn Signer,
+    renditions: &[Rendition],
+    output_path: &Path,
+    audit_log: Option<&AuditLog>,
+) -> Result<()> {
+    check_outputs(renditions)?;
+    for rendition in renditions {
+        println!("Adding manifest to: {:?}", rendition.init);
+        let init_name = rendition.init.file_name().context("invalid file name")?;
+        let output_dir = output_path.join(&rendition.output_dir);
+        sign_rendition(
+            builder,
+            signer,
+            &rendition.init,
+            &rendition.fragments,
+            &output_dir,
+            init_name,
+        )?;
+        if let Some(audit_log) = audit_log {
+            let signed = output_dir.join(init_name);
+            record(
+                audit_log,
+                &rendition.init,
+                &signed,
+                rendition.fragments.len(),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Records the signing of the `init` segment with a number of fragments to `signed`.
+pub fn record(audit_log: &AuditLog, init: &Path, signed: &Path, fragments: usize) -> Result<()> {
+    let reader = Reader::from_file(signed).ok();
+    audit_log.record(Flow::Fragment { fragments }, init, signed, reader.as_ref())
+}
+
+/// Signs an init segment and its fragments, writing the fragments to the `output_dir` folder
+/// and the init segment to `init_name` in it.
+pub fn sign_rendition(
+    builder: &mut Builder,
+    signer: &dyn Signer,
+    init: &Path,
+    fragments: &Vec<PathBuf>,
+    output_dir: &Path,
+    init_name: &OsStr,
+) -> Result<()> {
+    let source_name = init.file_name().context("invalid file name")?;
+    create_dir_all(output_dir)?;
+
+    // Builder::sign_fragmented_files takes the asset format from the extension of the
+    // output folder and requires that folder not to exist yet, so sign into a staging
+    // folder named after the init segment and move the results into place.
+    let staging = tempfile::tempdir_in(output_dir)?;
+    let staged = staging.path().join(source_name);
+    builder.sign_fragmented_files(signer, init, fragments, &staged)?;
+
+    for entry in std::fs::read_dir(&staged)? {
+        let entry = entry?;
+        let name = match entry.file_name() == source_name {
+            true => init_name.to_owned(),
+            false => entry.file_name(),
+        };
+        std::fs::rename(entry.path(), output_dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// The result of validating one init segment and the fragments that go with it.
+pub struct FragmentedValidation {
+    pub init: PathBuf,
+    pub reader: Option<Reader>,
+    /// Failures that apply to the init segment or the manifest as a whole.
+    pub init_failures: Vec<String>,
+    /// Failures traced back to a specific fragment file.
+    pub fragment_failures: Vec<(PathBuf, String)>,
+}
+
+impl FragmentedValidation {
+    pub fn passed(&self) -> bool {
+        self.init_failures.is_empty() && self.fragment_failures.is_empty()
+    }
+}
+
+// a failed validation status, kept apart from its message so failures of a whole rendition
+// can be matched with the failures of its fragments
+struct Failure {
+    code: String,
+    url: Option<String>,
+    message: String,
+}
+
+fn failed_statuses(reader: &Reader) -> Vec<Failure> {
+    reader
+        .validation_status()
+        .unwrap_or_default()
+        .iter()
+        .filter(|v| !v.passed())
+        .map(|v| Failure {
+            code: v.code().to_owned(),
+            url: v.url().map(str::to_owned),
+            message: match v.explanation() {
+                Some(explanation) => format!("{} ({})", v.code(), explanation),
+                None => v.code().to_string(),
+            },
+        })
+        .collect()
+}
+
+// the failures of a whole rendition that no fragment failed with the same status code for
+// the same manifest item, which belong to the init segment
+fn unexplained(failures: Vec<Failure>, fragment_failures: &[(PathBuf, Failure)]) -> Vec<String> {
+    failures
+        .into_iter()
+        .filter(|failure| {
+            !fragment_failures
+                .iter()
+                .any(|(_, other)| other.code == failure.code && other.url == failure.url)
+        })
+        .map(|failure| failure.message)
+        .collect()
+}
+
+// validates each fragment on its own against the init segment so failures can be
+// attributed to the fragment files that caused them
+fn validate_fragments(init: &Path, fragments: &[PathBuf]) -> Result<Vec<(PathBuf, Failure)>> {
+    let format = c2pa::format_from_path(init).context("unsupported init segment type")?;
+    let mut failures = Vec::new();
+    for fragment in fragments {
+        let result = Reader::from_fragment(&format, File::open(init)?, File::open(fragment)?);
+        match result {
+            Ok(reader) => {
+                for failure in failed_statuses(&reader) {
+                    failures.push((fragment.clone(), failure));
+                }
+            }
+            Err(e) => {
+                let message = special_errs(e).to_string();
+                let failure = Failure {
+                    code: message.clone(),
+                    url: None,
+                    message,
+                };
+                failures.push((fragment.clone(), failure));
+            }
+        }
+    }
+    Ok(failures)
+}
+
+/// Validates every rendition, reporting each failure to stderr.
+pub fn verify_renditions(renditions: &[Rendition]) -> Result<Vec<FragmentedValidation>> {
+    let mut results = Vec::new();
+
+    for rendition in renditions {
+        let Rendition {
+            init, fragments, ..
+        } = rendition;
+
+        println!("Verifying manifest: {:?}", init);
+        let mut result = FragmentedValidation {
+            init: init.clone(),
+            reader: None,
+            init_failures: Vec::new(),
+            fragment_failures: Vec::new(),
+        };
+        match Reader::from_fragmented_files(init, fragments) {
+            Ok(reader) => {
+                let failures = failed_statuses(&reader);
+                if !failures.is_empty() {
+                    let fragment_failures = validate_fragments(init, fragments)?;
+                    result.init_failures = unexplained(failures, &fragment_failures);
+                    result.fragment_failures = fragment_failures
+                        .into_iter()
+                        .map(|(fragment, failure)| (fragment, failure.message))
+                        .collect();
+                }
+                result.reader = Some(reader);
+            }
+            Err(e) => result.init_failures.push(special_errs(e).to_string()),
+        }
+
+        for failure in &result.init_failures {
+            eprintln!(
+                "Error validating init segment {:?}: {}",
+                result.init, failure
+            );
+        }
+        for (fragment, failure) in &result.fragment_failures {
+            eprintln!("Error validating fragment {:?}: {}", fragment, failure);
+        }
+
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    #[test]
+    fn test_natural_order() {
+        let mut names: Vec<PathBuf> = ["seg-10.m4s", "seg-2.m4s", "seg-1.m4s", "seg-02a.m4s"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            ["seg-1.m4s", "seg-2.m4s", "seg-02a.m4s", "seg-10.m4s"]
+                .iter()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_glob_root() {
+        assert_eq!(
+            glob_root(Path::new("/media/*/video/init.mp4")),
+            PathBuf::from("/media")
+        );
+        assert_eq!(
+            glob_root(Path::new("media/**/init.mp4")),
+            PathBuf::from("media")
+        );
+        assert_eq!(
+            glob_root(Path::new("/media/video/init.mp4")),
+            PathBuf::from("/media/video")
+        );
+    }
+
+    #[test]
+    fn test_output_preserves_structure() {
+        let tempdir = tempfile::tempdir().unwrap();
+        for dir in ["a/video", "b/video"] {
+            let dir = tempdir.path().join(dir);
+            create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("init.mp4"), b"init").unwrap();
+            std::fs::write(dir.join("seg-1.m4s"), b"seg").unwrap();
+        }
+
+        let pattern = tempdir.path().join("*/video/init.mp4");
+        let renditions = find_renditions(&pattern, Path::new("seg-*.m4s")).unwrap();
+        let dirs: Vec<_> = renditions.iter().map(|r| r.output_dir.clone()).collect();
+        assert_eq!(
+            dirs,
+            vec![PathBuf::from("a/video"), PathBuf::from("b/video")]
+        );
+        check_outputs(&renditions).unwrap();
+
+        let single = tempdir.path().join("a/video/init.mp4");
+        let renditions = find_renditions(&single, Path::new("seg-*.m4s")).unwrap();
+        assert_eq!(renditions[0].output_dir, PathBuf::from("video"));
+    }
+
+    #[test]
+    fn test_unexplained_failures() {
+        const HASH_URL: &str = "self#jumbf=c2pa.assertions/c2pa.hash.bmff.v2";
+        let failure = |code: &str, url: Option<&str>, explanation: &str| Failure {
+            code: code.to_owned(),
+            url: url.map(str::to_owned),
+            message: format!("{} ({})", code, explanation),
+        };
+        let failures = || {
+            vec![
+                failure(
+                    "assertion.bmffHash.mismatch",
+                    Some(HASH_URL),
+                    "hash mismatch",
+                ),
+                failure(
+                    "assertion.bmffHash.mismatch",
+                    Some("self#jumbf=c2pa.assertions/c2pa.hash.bmff.v2__1"),
+                    "hash mismatch",
+                ),
+                failure(
+                    "claimSignature.mismatch",
+                    None,
+                    "claim signature is not valid",
+                ),
+            ]
+        };
+        let fragment_failures = vec![(
+            PathBuf::from("seg-2.m4s"),
+            failure(
+                "assertion.bmffHash.mismatch",
+                Some(HASH_URL),
+                "fragment hash mismatch",
+            ),
+        )];
+
+        // a fragment only explains failures of the same status code for the same item
+        assert_eq!(
+            unexplained(failures(), &fragment_failures),
+            vec![
+                "assertion.bmffHash.mismatch (hash mismatch)".to_owned(),
+                "claimSignature.mismatch (claim signature is not valid)".to_owned()
+            ]
+        );
+        assert_eq!(unexplained(failures(), &[]).len(), 3);
+    }
+
+    #[test]
+    fn test_check_outputs_collision() {
+        let rendition = |init: &str| Rendition {
+            init: PathBuf::from(init),
+            fragments: vec![PathBuf::from("video/seg-1.m4s")],
+            output_dir: PathBuf::from("video"),
+        };
+        let err = check_outputs(&[rendition("video/init_a.mp4"), rendition("video/init_b.mp4")])
+            .unwrap_err();
+        assert!(err.to_string().contains("seg-1.m4s"));
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use reqwest::blocking::Client;
+use url::Url;
+
+use crate::callback_signer::{
+    json_request, json_response, retry, CallbackSignerConfig, SignCallback, SignContext,
+    SignerProcessArgs, SignerProtocol,
+};
+
+/// Default time to wait for the signing service to respond.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn parse_header(s: &str) -> Result<(String, String)> {
+    let (name, value) = s.split_once(':').context("expected NAME: VALUE")?;
+    Ok((name.trim().to_owned(), value.trim().to_owned()))
+}
+
+/// Options for calling the signer_url service.
+#[derive(Args, Clone, Debug, Default)]
+pub(crate) struct HttpSignerArgs {
+    /// Header to send to the signer_url service, as "NAME: VALUE". Can be repeated.
+    #[clap(
+        long = "signer-header",
+        value_name = "NAME: VALUE",
+        value_parser = parse_header,
+        requires = "signer_url"
+    )]
+    pub headers: Vec<(String, String)>,
+
+    /// Environment variable holding a bearer token to send to the signer_url service.
+    #[clap(long = "signer-token-env", value_name = "VAR", requires = "signer_url")]
+    pub token_env: Option<String>,
+
+    /// PEM certificate to authenticate to the signer_url service with, for mutual TLS.
+    #[clap(
+        long = "signer-client-cert",
+        value_name = "PEM",
+        requires_all = ["signer_url", "client_key"]
+    )]
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM PKCS#8 private key of the signer-client-cert certificate.
+    #[clap(
+        long = "signer-client-key",
+        value_name = "PEM",
+        requires = "client_cert"
+    )]
+    pub client_key: Option<PathBuf>,
+
+    /// PEM certificate of a CA to trust for the signer_url service, in addition to the system roots.
+    #[clap(long = "signer-ca-cert", value_name = "PEM", requires = "signer_url")]
+    pub ca_cert: Option<PathBuf>,
+}
+
+/// A [SignCallback] that sends the bytes to sign to an HTTP signing service.
+///
+/// With the raw protocol the claim bytes are POSTed as the request body and the response
+/// body is the signature. With the JSON protocol the same JSON request and response as
+/// for the external process are used.
+pub(crate) struct HttpSigner {
+    config: CallbackSignerConfig,
+    url: Url,
+    client: Client,
+    headers: Vec<(String, String)>,
+    protocol: SignerProtocol,
+    context: SignContext,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl HttpSigner {
+    pub fn new(
+        config: CallbackSignerConfig,
+        url: Url,
+        args: &HttpSignerArgs,
+        process: &SignerProcessArgs,
+    ) -> Result<Self> {
+        let timeout = process
+            .timeout
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TIMEOUT);
+        let mut client = Client::builder().timeout(timeout);
+        if let (Some(cert), Some(key)) = (&args.client_cert, &args.client_key) {
+            let cert = std::fs::read(cert)
+                .context(format!("Reading signer client certificate: {:?}", cert))?;
+            let key =
+                std::fs::read(key).context(format!("Reading signer client key: {:?}", key))?;
+            let identity = reqwest::Identity::from_pkcs8_pem(&cert, &key)
+                .context("Invalid signer client certificate or key")?;
+            client = client.identity(identity);
+        }
+        if let Some(ca_cert) = &args.ca_cert {
+            let pem = std::fs::read(ca_cert)
+                .context(format!("Reading signer CA certificate: {:?}", ca_cert))?;
+            let cert = reqwest::Certificate::from_pem(&pem)
+                .context(format!("Invalid signer CA certificate: {:?}", ca_cert))?;
+            client = client.add_root_certificate(cert);
+        }
+
+        let mut headers = args.headers.clone();
+        if let Some(var) = &args.token_env {
+            let token =
+                std::env::var(var).context(format!("The environment variable {var} is not set"))?;
+            headers.push(("Authorization".to_owned(), format!("Bearer {token}")));
+        }
+
+        Ok(Self {
+            config,
+            url,
+            client: client.build()?,
+            headers,
+            protocol: SignerProtocol::Raw,
+            context: SignContext::default(),
+            retries: process.retries,
+            retry_delay: Duration::from_millis(process.retry_delay),
+        })
+    }
+
+    /// Sets the protocol used to talk to the service and what is sent with it.
+    pub fn with_protocol(mut self, protocol: SignerProtocol, context: SignContext) -> Self {
+        self.protocol = protocol;
+        self.context = context;
+        self
+    }
+
+    /// Sends the request once. Failures worth retrying, where the service couldn't be reached,
+    /// timed out or had a server error, are returned as `Ok(Err(..))`.
+    fn post(&self, body: &[u8]) -> Result<Result<Vec<u8>, String>> {
+        let content_type = match self.protocol {
+            SignerProtocol::Raw => "application/octet-stream",
+            SignerProtocol::Json => "application/json",
+        };
+        let mut request = self
+            .client
+            .post(self.url.clone())
+            .header("Content-Type", content_type)
+            .header("C2PA-Alg", self.config.alg.to_string())
+            .header("C2PA-Reserve-Size", self.config.reserve_size().to_string())
+            .body(body.to_vec());
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        let response = match request.send() {
+            Ok(response) => response,
+            Err(e) => return Ok(Err(format!("Signing service {} failed: {}", self.url, e))),
+        };
+        let status = response.status();
+        let bytes = response.bytes()?.to_vec();
+        if status.is_server_error() {
+            return Ok(Err(format!(
+                "Signing service {} returned {}: {}",
+                self.url,
+                status,
+                String::from_utf8_lossy(&bytes)
+            )));
+        }
+        if !status.is_success() {
+            bail!(
+                "Signing service {} returned {}: {}",
+                self.url,
+                status,
+                String::from_utf8_lossy(&bytes)
+            );
+        }
+        Ok(Ok(bytes))
+    }
+}
+
+impl SignCallback for HttpSigner {
+    fn sign(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let body = match self.protocol {
+            SignerProtocol::Raw => bytes.to_vec(),
+            SignerProtocol::Json => json_request(&self.config, &self.context, bytes)?,
+        };
+
+        let response = retry(self.retries, self.retry_delay, || self.post(&body))?;
+
+        if response.is_empty() {
+            bail!("Signing service {} returned no signature bytes", self.url);
+        }
+
+        match self.protocol {
+            SignerProtocol::Raw => Ok(response),
+            SignerProtocol::Json => json_response(&self.config, &response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use c2pa::SigningAlg;
+    use httpmock::prelude::*;
+
+    use super::*;
+    use crate::signer::SignConfig;
+
+    fn es256_config() -> CallbackSignerConfig {
+        let sign_config = SignConfig {
+            alg: Some(SigningAlg::Es256.to_string()),
+            sign_cert: Some(PathBuf::from("sample/es256_certs.pem")),
+            ..Default::default()
+        };
+        CallbackSignerConfig::new(&sign_config, Some(1024), None).unwrap()
+    }
+
+    fn http_signer(server: &MockServer, args: &HttpSignerArgs) -> HttpSigner {
+        HttpSigner::new(
+            es256_config(),
+            Url::parse(&server.url("/sign")).unwrap(),
+            args,
+            &SignerProcessArgs {
+                retry_delay: 1,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_http_signer_raw_protocol() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/sign")
+                .header("content-type", "application/octet-stream")
+                .header("c2pa-alg", "es256")
+                .header("c2pa-reserve-size", "1024")
+                .header("x-api-key", "secret")
+                .body("claim");
+            then.status(200).body([1, 2, 3]);
+        });
+
+        let args = HttpSignerArgs {
+            headers: vec![parse_header("X-Api-Key: secret").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(
+            http_signer(&server, &args).sign(b"claim").unwrap(),
+            vec![1, 2, 3]
+        );
+        mock.assert();
+    }
+
+    #[test]
+    fn test_http_signer_json_protocol_with_token() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/sign")
+                .header("content-type", "application/json")
+                .header("authorization", "Bearer token-1")
+                .json_body_partial(r#"{"data": "Y2xhaW0=", "alg": "es256"}"#);
+            then.status(200).body(r#"{"signature": "AQID"}"#);
+        });
+
+        std::env::set_var("C2PATOOL_TEST_SIGNER_TOKEN", "token-1");
+        let args = HttpSignerArgs {
+            token_env: Some("C2PATOOL_TEST_SIGNER_TOKEN".to_owned()),
+            ..Default::default()
+        };
+        let signer =
+            http_signer(&server, &args).with_protocol(SignerProtocol::Json, SignContext::default());
+        assert_eq!(signer.sign(b"claim").unwrap(), vec![1, 2, 3]);
+        mock.assert();
+    }
+
+    #[test]
+    fn test_http_signer_errors() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/sign");
+            then.status(403).body("key not allowed");
+        });
+        let error = http_signer(&server, &HttpSignerArgs::default())
+            .sign(b"claim")
+            .unwrap_err();
+        assert!(error.to_string().contains("403"));
+        assert!(error.to_string().contains("key not allowed"));
+        // client errors are not retried
+        mock.assert_hits(1);
+
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/sign");
+            then.status(503);
+        });
+        let mut signer = http_signer(&server, &HttpSignerArgs::default());
+        signer.retries = 2;
+        assert!(signer.sign(b"claim").is_err());
+        mock.assert_hits(3);
+    }
+
+    #[test]
+    fn test_http_signer_client_cert() {
+        let args = HttpSignerArgs {
+            client_cert: Some(PathBuf::from("sample/es256_certs.pem")),
+            client_key: Some(PathBuf::from("sample/es256_private.key")),
+            ca_cert: Some(PathBuf::from("sample/trust_anchors.pem")),
+            ..Default::default()
+        };
+        let url = Url::parse("https://localhost/sign").unwrap();
+        let process = SignerProcessArgs::default();
+        assert!(HttpSigner::new(es256_config(), url.clone(), &args, &process).is_ok());
+
+        let args = HttpSignerArgs {
+            client_key: Some(PathBuf::from("sample/test.json")),
+            ..args
+        };
+        let error = HttpSigner::new(es256_config(), url, &args, &process)
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .contains("Invalid signer client certificate"));
+    }
+}
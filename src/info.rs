@@ -15,8 +15,19 @@ use std::{io::Cursor, path::Path};
 use anyhow::Result;
 use c2pa::{IngredientOptions, Reader};
 
+use crate::{
+    remote::{self, RemoteResolver},
+    sidecar,
+};
+
 /// display additional C2PA information about the asset (not json formatted)
-pub fn info(path: &Path) -> Result<()> {
+pub fn info(path: &Path, remote: &RemoteResolver) -> Result<()> {
+    if !remote.is_empty() {
+        if let Some(url) = remote::remote_url(path)? {
+            return remote_info(path, &url, remote);
+        }
+    }
+
     struct Options {}
     impl IngredientOptions for Options {
         fn thumbnail(&self, _path: &Path) -> Option<(String, Vec<u8>)> {
@@ -76,6 +87,44 @@ pub fn info(path: &Path) -> Result<()> {
     Ok(())
 }
 
+// display information about an asset whose remote manifest is read from a local mapping
+fn remote_info(path: &Path, url: &str, remote: &RemoteResolver) -> Result<()> {
+    let (manifest_data, source) = remote.fetch(url)?;
+    let reader = sidecar::validate(path, &manifest_data)?;
+
+    let file_size = std::fs::metadata(path)?.len();
+    if let Some(title) = path.file_name() {
+        println!("Information for {}", title.to_string_lossy());
+    }
+    println!("Cloud URL = {url}");
+    println!("Resolved locally from {source}");
+    println!(
+        "Remote manifest store size = {} (file size = {})",
+        manifest_data.len(),
+        file_size
+    );
+    let failures: Vec<_> = reader
+        .validation_status()
+        .unwrap_or_default()
+        .iter()
+        .filter(|s| !s.passed())
+        .collect();
+    if failures.is_empty() {
+        println!("Validated");
+    } else {
+        println!("Validation issues:");
+        for status in failures {
+            println!("   {}", status.code());
+        }
+    }
+    match reader.iter_manifests().count() {
+        0 => println!("No manifests"),
+        1 => println!("One manifest"),
+        n => println!("{n} manifests"),
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod tests {
     #![allow(clippy::expect_used)]
@@ -86,6 +135,10 @@ pub mod tests {
     fn test_manifest_config() {
         const SOURCE_PATH: &str = "tests/fixtures/C.jpg";
 
-        info(&std::path::PathBuf::from(SOURCE_PATH)).expect("info");
+        info(
+            &std::path::PathBuf::from(SOURCE_PATH),
+            &RemoteResolver::default(),
+        )
+        .expect("info");
     }
 }
@@ -0,0 +1,317 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+use std::{io::Write, path::Path};
+
+use anyhow::{bail, Context, Result};
+use c2pa::SigningAlg;
+use openssl::{
+    asn1::{Asn1Integer, Asn1Time},
+    bn::{BigNum, MsbOption},
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    x509::{
+        extension::{
+            AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage,
+            SubjectKeyIdentifier,
+        },
+        X509Name, X509,
+    },
+};
+
+use crate::preflight::{check_sign_cert, DEFAULT_TRUST_CONFIG};
+
+/// Every algorithm keys are generated for when none are asked for.
+const ALL_ALGS: [SigningAlg; 7] = [
+    SigningAlg::Es256,
+    SigningAlg::Es384,
+    SigningAlg::Es512,
+    SigningAlg::Ps256,
+    SigningAlg::Ps384,
+    SigningAlg::Ps512,
+    SigningAlg::Ed25519,
+];
+
+// names of the files written for each algorithm, like the ones in the sample folder
+fn key_file(alg: SigningAlg) -> String {
+    format!("{}_private.key", alg)
+}
+
+fn certs_file(alg: SigningAlg) -> String {
+    format!("{}_certs.pem", alg)
+}
+
+fn generate_key(alg: SigningAlg) -> Result<PKey<Private>> {
+    let curve = |nid| -> Result<PKey<Private>> {
+        let group = EcGroup::from_curve_name(nid)?;
+        Ok(PKey::from_ec_key(EcKey::generate(&group)?)?)
+    };
+    match alg {
+        SigningAlg::Es256 => curve(Nid::X9_62_PRIME256V1),
+        SigningAlg::Es384 => curve(Nid::SECP384R1),
+        SigningAlg::Es512 => curve(Nid::SECP521R1),
+        SigningAlg::Ps256 => Ok(PKey::from_rsa(Rsa::generate(2048)?)?),
+        SigningAlg::Ps384 => Ok(PKey::from_rsa(Rsa::generate(3072)?)?),
+        SigningAlg::Ps512 => Ok(PKey::from_rsa(Rsa::generate(4096)?)?),
+        SigningAlg::Ed25519 => Ok(PKey::generate_ed25519()?),
+    }
+}
+
+fn name(organization: &str, common_name: &str) -> Result<X509Name> {
+    let mut name = X509Name::builder()?;
+    name.append_entry_by_nid(Nid::ORGANIZATIONNAME, organization)?;
+    name.append_entry_by_nid(Nid::ORGANIZATIONALUNITNAME, "FOR TESTING ONLY")?;
+    name.append_entry_by_nid(Nid::COMMONNAME, common_name)?;
+    Ok(name.build())
+}
+
+/// Creates a certificate for `key`, issued by `issuer` or self-signed. CA certificates can
+/// issue certificates, the others have the key and extended key usages C2PA needs to sign.
+fn make_cert(
+    subject: &X509Name,
+    key: &PKey<Private>,
+    issuer: Option<(&X509, &PKey<Private>)>,
+    days: u32,
+    ca: bool,
+) -> Result<X509> {
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    let mut serial = BigNum::new()?;
+    serial.rand(127, MsbOption::MAYBE_ZERO, false)?;
+    let serial = Asn1Integer::from_bn(&serial)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(subject)?;
+    builder.set_issuer_name(
+        issuer
+            .map(|(cert, _)| cert.subject_name())
+            .unwrap_or(subject),
+    )?;
+    builder.set_pubkey(key)?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(days)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+
+    if ca {
+        builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+        builder.append_extension(
+            KeyUsage::new()
+                .critical()
+                .key_cert_sign()
+                .crl_sign()
+                .build()?,
+        )?;
+    } else {
+        builder.append_extension(BasicConstraints::new().critical().build()?)?;
+        builder.append_extension(
+            KeyUsage::new()
+                .critical()
+                .digital_signature()
+                .non_repudiation()
+                .build()?,
+        )?;
+        builder.append_extension(
+            ExtendedKeyUsage::new()
+                .critical()
+                .email_protection()
+                .other("1.3.6.1.5.5.7.3.36")
+                .build()?,
+        )?;
+    }
+    let ski = SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(ski)?;
+    let aki = AuthorityKeyIdentifier::new()
+        .keyid(true)
+        .build(&builder.x509v3_context(issuer.map(|(cert, _)| cert.as_ref()), None))?;
+    builder.append_extension(aki)?;
+
+    // Ed25519 signatures have no separate digest
+    let signing_key = issuer.map(|(_, key)| key).unwrap_or(key);
+    let digest = match signing_key.id() {
+        openssl::pkey::Id::ED25519 => MessageDigest::null(),
+        _ => MessageDigest::sha256(),
+    };
+    builder.sign(signing_key, digest)?;
+    Ok(builder.build())
+}
+
+// writes a private key so that only its owner can read it
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // the mode only applies to new files, so restrict a file being replaced too
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(data)
+}
+
+/// Writes a test root CA, an intermediate CA and a signing key and certificate chain
+/// for each of `algs` (or every algorithm if empty) to `folder`, with a trust anchors file
+/// and trust config that validate them, then prints how to use them.
+pub(crate) fn keygen(folder: &Path, algs: &[SigningAlg], days: u32, force: bool) -> Result<()> {
+    let algs = if algs.is_empty() { &ALL_ALGS[..] } else { algs };
+
+    let mut files = vec![
+        "root_ca.key".to_owned(),
+        "root_ca.pem".to_owned(),
+        "intermediate_ca.key".to_owned(),
+        "intermediate_ca.pem".to_owned(),
+        "trust_anchors.pem".to_owned(),
+        "store.cfg".to_owned(),
+    ];
+    for alg in algs {
+        files.push(key_file(*alg));
+        files.push(certs_file(*alg));
+    }
+    if !force {
+        if let Some(existing) = files.iter().find(|f| folder.join(f).exists()) {
+            bail!(
+                "{:?} already exists. Use -f/--force to overwrite the files in {:?}",
+                folder.join(existing),
+                folder
+            );
+        }
+    }
+    std::fs::create_dir_all(folder).context(format!("Creating folder {:?}", folder))?;
+
+    let root_key = generate_key(SigningAlg::Es256)?;
+    let root = make_cert(
+        &name("C2PA Test Root CA", "C2PA Test Root CA")?,
+        &root_key,
+        None,
+        days,
+        true,
+    )?;
+    let intermediate_key = generate_key(SigningAlg::Es256)?;
+    let intermediate = make_cert(
+        &name("C2PA Test Intermediate CA", "C2PA Test Intermediate CA")?,
+        &intermediate_key,
+        Some((&root, &root_key)),
+        days,
+        true,
+    )?;
+
+    let write = |file: &str, data: &[u8]| -> Result<()> {
+        let path = folder.join(file);
+        std::fs::write(&path, data).context(format!("Writing {:?}", path))
+    };
+    let write_key = |file: &str, data: &[u8]| -> Result<()> {
+        let path = folder.join(file);
+        write_private(&path, data).context(format!("Writing {:?}", path))
+    };
+    write_key("root_ca.key", &root_key.private_key_to_pem_pkcs8()?)?;
+    write("root_ca.pem", &root.to_pem()?)?;
+    write_key(
+        "intermediate_ca.key",
+        &intermediate_key.private_key_to_pem_pkcs8()?,
+    )?;
+    write("intermediate_ca.pem", &intermediate.to_pem()?)?;
+    write("trust_anchors.pem", &root.to_pem()?)?;
+    // the extended key usages c2pa accepts for signing, time stamping and OCSP responses
+    write("store.cfg", DEFAULT_TRUST_CONFIG.as_bytes())?;
+
+    for alg in algs {
+        let key = generate_key(*alg)?;
+        let cert = make_cert(
+            &name(
+                "C2PA Test Signing Cert",
+                &format!("C2PA Test {} Signer", alg),
+            )?,
+            &key,
+            Some((&intermediate, &intermediate_key)),
+            days,
+            false,
+        )?;
+        let mut chain = cert.to_pem()?;
+        chain.extend(intermediate.to_pem()?);
+        let private_key = key.private_key_to_pem_pkcs8()?;
+        check_sign_cert(&certs_file(*alg), &chain, Some(&private_key), *alg, None)?;
+        write_key(&key_file(*alg), &private_key)?;
+        write(&certs_file(*alg), &chain)?;
+    }
+
+    let folder = std::fs::canonicalize(folder)?;
+    println!(
+        "Wrote a test CA and signing certificates to {:?}. They are for development and testing only.\n",
+        folder
+    );
+    println!("To sign with them, add the signing settings for an algorithm to the manifest definition:\n");
+    for alg in algs {
+        let snippet = serde_json::json!({
+            "alg": alg.to_string(),
+            "private_key": folder.join(key_file(*alg)),
+            "sign_cert": folder.join(certs_file(*alg)),
+        });
+        println!("{}", serde_json::to_string_pretty(&snippet)?);
+    }
+    println!(
+        "\nTo validate signed assets against the test CA:\n\n  c2patool <ASSET> trust --trust_anchors {:?} --trust_config {:?}",
+        folder.join("trust_anchors.pem"),
+        folder.join("store.cfg")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    #[test]
+    fn test_keygen_writes_valid_chains() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let algs = [SigningAlg::Es384, SigningAlg::Ps256, SigningAlg::Ed25519];
+        keygen(tempdir.path(), &algs, 30, false).unwrap();
+
+        let root =
+            X509::from_pem(&std::fs::read(tempdir.path().join("root_ca.pem")).unwrap()).unwrap();
+        for alg in algs {
+            let certs = std::fs::read(tempdir.path().join(certs_file(alg))).unwrap();
+            let key = std::fs::read(tempdir.path().join(key_file(alg))).unwrap();
+            check_sign_cert("test", &certs, Some(&key), alg, None).unwrap();
+
+            // the chain ends with the intermediate, issued by the root in the trust anchors
+            let chain = X509::stack_from_pem(&certs).unwrap();
+            assert_eq!(chain.len(), 2);
+            assert!(chain[1].verify(&root.public_key().unwrap()).unwrap());
+        }
+        assert!(!tempdir.path().join(key_file(SigningAlg::Es256)).exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for file in [
+                "root_ca.key",
+                "intermediate_ca.key",
+                &key_file(SigningAlg::Es384),
+            ] {
+                let metadata = std::fs::metadata(tempdir.path().join(file)).unwrap();
+                assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+            }
+        }
+
+        // existing files are only replaced with force
+        let error = keygen(tempdir.path(), &algs, 30, false).unwrap_err();
+        assert!(error.to_string().contains("already exists"));
+        keygen(tempdir.path(), &algs, 30, true).unwrap();
+    }
+}
@@ -0,0 +1,536 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+//! Signers that sign claims with a key held in a cloud key management service.
+//!
+//! The services are called through their REST APIs, so an `endpoint` can point them at a
+//! local emulator such as localstack instead of the cloud.
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use c2pa::SigningAlg;
+use openssl::{
+    hash::{hash, MessageDigest},
+    pkey::PKey,
+    sign::Signer as HmacSigner,
+};
+use serde::Deserialize;
+use serde_json::json;
+use url::{Position, Url};
+
+use crate::callback_signer::SignCallback;
+
+const AWS_KMS_TARGET: &str = "TrentService.Sign";
+const GCP_KMS_ENDPOINT: &str = "https://cloudkms.googleapis.com";
+
+/// A signer backend given in the `signer` field of the manifest definition.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SignerDef {
+    /// A key held in a cloud key management service.
+    Kms(KmsConfig),
+}
+
+/// The key management services that can sign claims.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KmsProvider {
+    /// AWS Key Management Service.
+    Aws,
+    /// Google Cloud Key Management Service.
+    Gcp,
+}
+
+/// Where the signing key is and how to reach the service holding it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct KmsConfig {
+    pub provider: KmsProvider,
+    /// The AWS key ID or ARN, or the Google Cloud key version resource name.
+    pub key_id: String,
+    /// The AWS region. Defaults to the `AWS_REGION` or `AWS_DEFAULT_REGION` environment variable.
+    pub region: Option<String>,
+    /// URL of the service, to use a local emulator. Defaults to the `AWS_ENDPOINT_URL`
+    /// environment variable for AWS, then the public service.
+    pub endpoint: Option<String>,
+}
+
+impl KmsConfig {
+    /// Returns the [SignCallback] that signs with this key.
+    pub fn callback(&self, alg: SigningAlg) -> Result<Box<dyn SignCallback>> {
+        Ok(match self.provider {
+            KmsProvider::Aws => Box::new(AwsKmsSigner::new(self, alg)?),
+            KmsProvider::Gcp => Box::new(GcpKmsSigner::new(self, alg)?),
+        })
+    }
+}
+
+// the digest signed for an algorithm, KMS APIs sign a digest computed by the caller
+fn digest(alg: SigningAlg) -> Result<(MessageDigest, &'static str)> {
+    Ok(match alg {
+        SigningAlg::Es256 | SigningAlg::Ps256 => (MessageDigest::sha256(), "sha256"),
+        SigningAlg::Es384 | SigningAlg::Ps384 => (MessageDigest::sha384(), "sha384"),
+        SigningAlg::Es512 | SigningAlg::Ps512 => (MessageDigest::sha512(), "sha512"),
+        SigningAlg::Ed25519 => bail!("alg ed25519 is not supported by this KMS signer"),
+    })
+}
+
+/// Signs with a key in AWS KMS, using its JSON API with Signature Version 4 authentication.
+///
+/// Credentials are read from the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+/// `AWS_SESSION_TOKEN` environment variables.
+pub struct AwsKmsSigner {
+    key_id: String,
+    alg: SigningAlg,
+    region: String,
+    endpoint: Url,
+    credentials: AwsCredentials,
+}
+
+#[derive(Clone, Debug)]
+struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AwsSignResponse {
+    signature: String,
+}
+
+impl AwsKmsSigner {
+    pub fn new(config: &KmsConfig, alg: SigningAlg) -> Result<Self> {
+        let region = config
+            .region
+            .clone()
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+            .context("The AWS KMS signer needs a region, set \"region\" or AWS_REGION")?;
+        let endpoint = config
+            .endpoint
+            .clone()
+            .or_else(|| std::env::var("AWS_ENDPOINT_URL").ok())
+            .unwrap_or_else(|| format!("https://kms.{region}.amazonaws.com/"));
+        let endpoint = Url::parse(&endpoint)
+            .with_context(|| format!("The AWS KMS endpoint {} is not valid", endpoint))?;
+        let credentials = AwsCredentials {
+            access_key_id: std::env::var("AWS_ACCESS_KEY_ID")
+                .context("The AWS KMS signer needs AWS_ACCESS_KEY_ID")?,
+            secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY")
+                .context("The AWS KMS signer needs AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        };
+        Self::signing_algorithm(alg)?;
+        Ok(Self {
+            key_id: config.key_id.clone(),
+            alg,
+            region,
+            endpoint,
+            credentials,
+        })
+    }
+
+    fn signing_algorithm(alg: SigningAlg) -> Result<&'static str> {
+        Ok(match alg {
+            SigningAlg::Es256 => "ECDSA_SHA_256",
+            SigningAlg::Es384 => "ECDSA_SHA_384",
+            SigningAlg::Es512 => "ECDSA_SHA_512",
+            SigningAlg::Ps256 => "RSASSA_PSS_SHA_256",
+            SigningAlg::Ps384 => "RSASSA_PSS_SHA_384",
+            SigningAlg::Ps512 => "RSASSA_PSS_SHA_512",
+            SigningAlg::Ed25519 => bail!("alg ed25519 is not supported by the AWS KMS signer"),
+        })
+    }
+}
+
+impl SignCallback for AwsKmsSigner {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        // KMS limits raw messages to 4096 bytes, so the digest is sent instead
+        let (md, _) = digest(self.alg)?;
+        let body = json!({
+            "KeyId": self.key_id,
+            "Message": BASE64.encode(hash(md, data)?),
+            "MessageType": "DIGEST",
+            "SigningAlgorithm": Self::signing_algorithm(self.alg)?,
+        })
+        .to_string();
+
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let headers = aws_sign_v4(
+            &self.endpoint,
+            &body,
+            &amz_date,
+            &self.region,
+            &self.credentials,
+        )?;
+
+        let mut request = reqwest::blocking::Client::new()
+            .post(self.endpoint.clone())
+            .body(body);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let response = request
+            .send()
+            .with_context(|| format!("Failed to reach AWS KMS at {}", self.endpoint))?;
+        let status = response.status();
+        let text = response.text()?;
+        if !status.is_success() {
+            bail!("AWS KMS returned {}: {}", status, text);
+        }
+        let response: AwsSignResponse =
+            serde_json::from_str(&text).context("AWS KMS returned an invalid response")?;
+        BASE64
+            .decode(response.signature)
+            .context("AWS KMS returned a signature that is not valid base64")
+    }
+}
+
+// Returns the headers of a KMS Sign request authenticated with AWS Signature Version 4.
+fn aws_sign_v4(
+    endpoint: &Url,
+    body: &str,
+    amz_date: &str,
+    region: &str,
+    credentials: &AwsCredentials,
+) -> Result<Vec<(&'static str, String)>> {
+    let host = &endpoint[Position::BeforeHost..Position::AfterPort];
+    let path = match endpoint.path() {
+        "" => "/",
+        path => path,
+    };
+
+    let mut headers = vec![
+        ("content-type", "application/x-amz-json-1.1".to_owned()),
+        ("host", host.to_owned()),
+        ("x-amz-date", amz_date.to_owned()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    headers.push(("x-amz-target", AWS_KMS_TARGET.to_owned()));
+
+    let authorization = aws_authorization(
+        "POST",
+        path,
+        &headers,
+        body,
+        amz_date,
+        region,
+        "kms",
+        credentials,
+    )?;
+
+    headers.retain(|(name, _)| *name != "host");
+    headers.push(("authorization", authorization));
+    Ok(headers)
+}
+
+// Returns the AWS Signature Version 4 Authorization header of a request without a query
+// string, signing all of its `headers`, whose names must be lowercase and sorted.
+#[allow(clippy::too_many_arguments)]
+fn aws_authorization(
+    method: &str,
+    path: &str,
+    headers: &[(&str, String)],
+    body: &str,
+    amz_date: &str,
+    region: &str,
+    service: &str,
+    credentials: &AwsCredentials,
+) -> Result<String> {
+    let date = &amz_date[..8];
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{value}\n"))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{method}\n{path}\n\n{canonical_headers}\n{signed_headers}\n{}",
+        hex(&hash(MessageDigest::sha256(), body.as_bytes())?)
+    );
+
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex(&hash(
+            MessageDigest::sha256(),
+            canonical_request.as_bytes()
+        )?)
+    );
+    let key = aws_signing_key(&credentials.secret_access_key, date, region, service)?;
+    let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes())?);
+    Ok(format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+        credentials.access_key_id
+    ))
+}
+
+fn aws_signing_key(secret: &str, date: &str, region: &str, service: &str) -> Result<Vec<u8>> {
+    let key = hmac_sha256(format!("AWS4{secret}").as_bytes(), date.as_bytes())?;
+    let key = hmac_sha256(&key, region.as_bytes())?;
+    let key = hmac_sha256(&key, service.as_bytes())?;
+    hmac_sha256(&key, b"aws4_request")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let key = PKey::hmac(key)?;
+    Ok(HmacSigner::new(MessageDigest::sha256(), &key)?.sign_oneshot_to_vec(data)?)
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Signs with a key version in Google Cloud KMS, using its REST API.
+///
+/// The OAuth access token is read from the `GOOGLE_OAUTH_ACCESS_TOKEN` environment variable,
+/// for example from `gcloud auth print-access-token`.
+pub struct GcpKmsSigner {
+    key_id: String,
+    alg: SigningAlg,
+    endpoint: Url,
+    access_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GcpSignResponse {
+    signature: String,
+}
+
+impl GcpKmsSigner {
+    pub fn new(config: &KmsConfig, alg: SigningAlg) -> Result<Self> {
+        let endpoint = config.endpoint.as_deref().unwrap_or(GCP_KMS_ENDPOINT);
+        let endpoint = Url::parse(endpoint)
+            .with_context(|| format!("The Google Cloud KMS endpoint {} is not valid", endpoint))?;
+        let access_token = std::env::var("GOOGLE_OAUTH_ACCESS_TOKEN").ok();
+        if access_token.is_none() && config.endpoint.is_none() {
+            bail!("The Google Cloud KMS signer needs GOOGLE_OAUTH_ACCESS_TOKEN");
+        }
+        Ok(Self {
+            key_id: config.key_id.clone(),
+            alg,
+            endpoint,
+            access_token,
+        })
+    }
+}
+
+impl SignCallback for GcpKmsSigner {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        // Ed25519 keys sign the data itself, other keys sign a digest of it
+        let body = match self.alg {
+            SigningAlg::Ed25519 => json!({ "data": BASE64.encode(data) }),
+            alg => {
+                let (md, name) = digest(alg)?;
+                json!({ "digest": { name: BASE64.encode(hash(md, data)?) } })
+            }
+        };
+
+        let base = self.endpoint.as_str().trim_end_matches('/');
+        let url = format!("{base}/v1/{}:asymmetricSign", self.key_id);
+        let mut request = reqwest::blocking::Client::new()
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+        if let Some(token) = &self.access_token {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .with_context(|| format!("Failed to reach Google Cloud KMS at {}", url))?;
+        let status = response.status();
+        let text = response.text()?;
+        if !status.is_success() {
+            bail!("Google Cloud KMS returned {}: {}", status, text);
+        }
+        let response: GcpSignResponse =
+            serde_json::from_str(&text).context("Google Cloud KMS returned an invalid response")?;
+        BASE64
+            .decode(response.signature)
+            .context("Google Cloud KMS returned a signature that is not valid base64")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use httpmock::prelude::*;
+
+    use super::*;
+
+    fn credentials() -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: "test".to_owned(),
+            secret_access_key: "test".to_owned(),
+            session_token: None,
+        }
+    }
+
+    fn sample_signature(data: &[u8]) -> Vec<u8> {
+        let key =
+            PKey::private_key_from_pem(include_bytes!("../sample/es256_private.key")).unwrap();
+        openssl::sign::Signer::new(MessageDigest::sha256(), &key)
+            .unwrap()
+            .sign_oneshot_to_vec(data)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_aws_signing_key() {
+        // example from the AWS Signature Version 4 documentation
+        let key = aws_signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20150830",
+            "us-east-1",
+            "iam",
+        )
+        .unwrap();
+        assert_eq!(
+            hex(&key),
+            "c4afb1cc5771d871763a393e44b703571b55cc28424d1a5e86da6ed3c154a4b9"
+        );
+    }
+
+    #[test]
+    fn test_aws_authorization() {
+        // the get-vanilla and post-vanilla cases of the AWS Signature Version 4 test suite
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_owned(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned(),
+            session_token: None,
+        };
+        let headers = [
+            ("host", "example.amazonaws.com".to_owned()),
+            ("x-amz-date", "20150830T123600Z".to_owned()),
+        ];
+        let credential = "Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request";
+        for (method, signature) in [
+            (
+                "GET",
+                "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31",
+            ),
+            (
+                "POST",
+                "5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b",
+            ),
+        ] {
+            let authorization = aws_authorization(
+                method,
+                "/",
+                &headers,
+                "",
+                "20150830T123600Z",
+                "us-east-1",
+                "service",
+                &credentials,
+            )
+            .unwrap();
+            assert_eq!(
+                authorization,
+                format!(
+                    "AWS4-HMAC-SHA256 {credential}, SignedHeaders=host;x-amz-date, Signature={signature}"
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_kms_signer_config() {
+        let def: SignerDef = serde_json::from_str(
+            r#"{"type": "kms", "provider": "aws", "key_id": "alias/c2pa", "region": "us-east-1"}"#,
+        )
+        .unwrap();
+        let SignerDef::Kms(config) = def;
+        assert_eq!(config.provider, KmsProvider::Aws);
+        assert_eq!(config.key_id, "alias/c2pa");
+        assert!(serde_json::from_str::<SignerDef>(r#"{"type": "hsm"}"#).is_err());
+    }
+
+    #[test]
+    fn test_aws_kms_sign() {
+        let server = MockServer::start();
+        let signature = sample_signature(b"claim");
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .header("x-amz-target", AWS_KMS_TARGET)
+                .header("content-type", "application/x-amz-json-1.1")
+                .header_exists("authorization")
+                .json_body_partial(r#"{"KeyId": "alias/c2pa", "MessageType": "DIGEST", "SigningAlgorithm": "ECDSA_SHA_256"}"#);
+            then.status(200)
+                .json_body(json!({ "KeyId": "alias/c2pa", "Signature": BASE64.encode(&signature) }));
+        });
+
+        let signer = AwsKmsSigner {
+            key_id: "alias/c2pa".to_owned(),
+            alg: SigningAlg::Es256,
+            region: "us-east-1".to_owned(),
+            endpoint: Url::parse(&server.base_url()).unwrap(),
+            credentials: credentials(),
+        };
+        assert_eq!(signer.sign(b"claim").unwrap(), signature);
+        mock.assert();
+    }
+
+    #[test]
+    fn test_aws_kms_reports_errors() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST);
+            then.status(400)
+                .body(r#"{"__type": "NotFoundException", "message": "Alias not found"}"#);
+        });
+
+        let signer = AwsKmsSigner {
+            key_id: "alias/missing".to_owned(),
+            alg: SigningAlg::Es256,
+            region: "us-east-1".to_owned(),
+            endpoint: Url::parse(&server.base_url()).unwrap(),
+            credentials: credentials(),
+        };
+        let error = signer.sign(b"claim").unwrap_err().to_string();
+        assert!(error.contains("400"));
+        assert!(error.contains("Alias not found"));
+    }
+
+    #[test]
+    fn test_gcp_kms_sign() {
+        let server = MockServer::start();
+        let signature = sample_signature(b"claim");
+        let key_id = "projects/p/locations/global/keyRings/r/cryptoKeys/k/cryptoKeyVersions/1";
+        let digest = BASE64.encode(hash(MessageDigest::sha256(), b"claim").unwrap());
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path(format!("/v1/{key_id}:asymmetricSign"))
+                .json_body(json!({ "digest": { "sha256": digest } }));
+            then.status(200)
+                .json_body(json!({ "signature": BASE64.encode(&signature) }));
+        });
+
+        let config = KmsConfig {
+            provider: KmsProvider::Gcp,
+            key_id: key_id.to_owned(),
+            region: None,
+            endpoint: Some(server.base_url()),
+        };
+        let signer = config.callback(SigningAlg::Es256).unwrap();
+        assert_eq!(signer.sign(b"claim").unwrap(), signature);
+        mock.assert();
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use c2pa::{Builder, Signer};
+use serde::{Deserialize, Serialize};
+
+use crate::{audit::AuditLog, fragment};
+
+/// Options for watching a folder of fragments.
+pub struct WatchOptions {
+    /// Where signing progress is saved so an interrupted run can resume.
+    pub checkpoint: PathBuf,
+    /// How often to look for new fragments.
+    pub poll_interval: Duration,
+    /// Stop after this long without new fragments, or run until killed if `None`.
+    pub idle_timeout: Option<Duration>,
+}
+
+/// Records the fragments signed so far for each init segment.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Checkpoint {
+    renditions: BTreeMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl Checkpoint {
+    /// Loads a checkpoint, returning an empty one if the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = std::fs::read_to_string(path)?;
+        serde_json::from_str(&json).context(format!("reading checkpoint {:?}", path))
+    }
+
+    /// Saves the checkpoint, replacing the previous file in one step.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+// Tracks fragment sizes between polls. A fragment is ready to sign once its size
+// has stopped changing, so files still being written are left for a later poll.
+#[derive(Default)]
+struct ReadyTracker {
+    sizes: HashMap<PathBuf, u64>,
+}
+
+impl ReadyTracker {
+    fn ready(&mut self, candidates: &[PathBuf]) -> Vec<PathBuf> {
+        let mut ready = Vec::new();
+        for path in candidates {
+            let Ok(size) = std::fs::metadata(path).map(|m| m.len()) else {
+                continue;
+            };
+            if self.sizes.insert(path.clone(), size) == Some(size) && size > 0 {
+                ready.push(path.clone());
+            }
+        }
+        ready
+    }
+}
+
+// removes the signed outputs of source fragments that no longer exist, returning whether
+// any were removed
+fn remove_deleted(signed: &mut Vec<PathBuf>, output_dir: &Path) -> Result<bool> {
+    let mut removed = false;
+    for fragment in signed.iter().filter(|f| !f.exists()) {
+        let name = fragment.file_name().context("invalid file name")?;
+        remove_output(&output_dir.join(name))?;
+        removed = true;
+    }
+    signed.retain(|f| f.exists());
+    Ok(removed)
+}
+
+fn remove_output(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).context(format!("removing {:?}", path))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Signs fragments as they appear in the folders of the init segments matching `init_pattern`.
+///
+/// The Merkle tree that binds fragments to a manifest is stored in the init segment, and
+/// each fragment holds its proof in that tree, so a tree can't grow without changing the
+/// fragments already signed. When new fragments are ready, the init segment is signed again
+/// with a tree over every fragment still in the source folder, and all of the rendition's
+/// fragments are written again with their new proofs. Each rendition keeps a single init
+/// segment that validates all of its fragments. The outputs of fragments deleted from the
+/// source folder are removed.
+pub fn watch_fragmented(
+    builder: &mut Builder,
+    signer: &dyn Signer,
+    init_pattern: &Path,
+    frag_pattern: &Path,
+    output_path: &Path,
+    options: &WatchOptions,
+    audit_log: Option<&AuditLog>,
+) -> Result<()> {
+    let mut checkpoint = Checkpoint::load(&options.checkpoint)?;
+    for (init, signed) in &checkpoint.renditions {
+        println!("Resuming {:?} with {} signed fragments", init, signed.len());
+    }
+
+    let mut tracker = ReadyTracker::default();
+    let mut last_activity = Instant::now();
+    loop {
+        let renditions = fragment::find_renditions(init_pattern, frag_pattern)?;
+        fragment::check_outputs(&renditions)?;
+
+        for found in renditions {
+            let output_dir = output_path.join(&found.output_dir);
+            let signed = checkpoint.renditions.entry(found.init.clone()).or_default();
+            let mut changed = remove_deleted(signed, &output_dir)?;
+
+            let candidates: Vec<PathBuf> = found
+                .fragments
+                .iter()
+                .filter(|f| !signed.contains(f))
+                .cloned()
+                .collect();
+            let new_fragments = tracker.ready(&candidates);
+            if new_fragments.len() != candidates.len() {
+                last_activity = Instant::now();
+            }
+
+            if !new_fragments.is_empty() {
+                // the signed fragments and the new ones, in the order of the source folder
+                let fragments: Vec<PathBuf> = found
+                    .fragments
+                    .iter()
+                    .filter(|f| signed.contains(f) || new_fragments.contains(f))
+                    .cloned()
+                    .collect();
+                let init_name = found.init.file_name().context("invalid file name")?;
+                fragment::sign_rendition(
+                    builder,
+                    signer,
+                    &found.init,
+                    &fragments,
+                    &output_dir,
+                    init_name,
+                )?;
+                if let Some(audit_log) = audit_log {
+                    let signed = output_dir.join(init_name);
+                    fragment::record(audit_log, &found.init, &signed, fragments.len())?;
+                }
+
+                println!(
+                    "Signed {} new fragments for {:?} ({} total)",
+                    new_fragments.len(),
+                    found.init,
+                    fragments.len()
+                );
+                *signed = fragments;
+                changed = true;
+                last_activity = Instant::now();
+            }
+
+            if changed {
+                checkpoint.save(&options.checkpoint)?;
+            }
+        }
+
+        if let Some(idle_timeout) = options.idle_timeout {
+            if last_activity.elapsed() >= idle_timeout {
+                println!("No new fragments for {:?}, stopping", idle_timeout);
+                return Ok(());
+            }
+        }
+        sleep(options.poll_interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("checkpoint.json");
+        assert_eq!(Checkpoint::load(&path).unwrap(), Checkpoint::default());
+
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.renditions.insert(
+            PathBuf::from("video/init.mp4"),
+            vec![PathBuf::from("video/seg-1.m4s")],
+        );
+        checkpoint.save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), checkpoint);
+    }
+
+    #[test]
+    fn test_remove_deleted() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let (source, output) = (tempdir.path().join("source"), tempdir.path().join("output"));
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(&output).unwrap();
+        for name in ["seg-1.m4s", "seg-2.m4s"] {
+            std::fs::write(source.join(name), name).unwrap();
+        }
+        for name in ["init.mp4", "seg-1.m4s", "seg-2.m4s"] {
+            std::fs::write(output.join(name), name).unwrap();
+        }
+        let mut signed = vec![source.join("seg-1.m4s"), source.join("seg-2.m4s")];
+        assert!(!remove_deleted(&mut signed, &output).unwrap());
+
+        std::fs::remove_file(source.join("seg-1.m4s")).unwrap();
+        assert!(remove_deleted(&mut signed, &output).unwrap());
+        assert_eq!(signed, vec![source.join("seg-2.m4s")]);
+        assert!(!output.join("seg-1.m4s").exists());
+        assert!(output.join("seg-2.m4s").exists());
+        assert!(output.join("init.mp4").exists());
+    }
+
+    #[test]
+    fn test_ready_waits_for_stable_size() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("seg-1.m4s");
+        let candidates = vec![path.clone()];
+        let mut tracker = ReadyTracker::default();
+
+        std::fs::write(&path, b"part").unwrap();
+        assert!(tracker.ready(&candidates).is_empty());
+        std::fs::write(&path, b"partial fragment").unwrap();
+        assert!(tracker.ready(&candidates).is_empty());
+        assert_eq!(tracker.ready(&candidates), candidates);
+    }
+}
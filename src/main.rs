@@ -22,30 +22,63 @@ use std::{
     io::Write,
     path::{Path, PathBuf},
     str::FromStr,
+    time::Duration,
 };
 
 use anyhow::{anyhow, bail, Context, Result};
-use c2pa::{Builder, ClaimGeneratorInfo, Error, Ingredient, ManifestDefinition, Reader, Signer};
-use clap::{Parser, Subcommand};
+use c2pa::{Builder, Error, Ingredient, Signer, SigningAlg};
+use clap::{ArgGroup, Parser, Subcommand};
 use log::debug;
 use serde::Deserialize;
 use signer::SignConfig;
 use url::Url;
 
 use crate::{
-    callback_signer::{CallbackSigner, CallbackSignerConfig, ExternalProcessRunner},
+    audit::{AuditLog, Flow},
+    callback_signer::{
+        CallbackSigner, CallbackSignerConfig, ExternalProcessRunner, SignContext,
+        SignerProcessArgs, SignerProtocol,
+    },
+    claim_generator::ClaimGeneratorArgs,
+    format::ReportFormat,
+    http_signer::{HttpSigner, HttpSignerArgs},
     info::info,
+    kms::SignerDef,
+    playlist::Playlist,
+    private_key::KeyPassphraseArgs,
+    remote::{RemoteMapping, RemoteResolver, StoreFolder},
+    training::TrainingMiningArgs,
+    tsa::{TsaArgs, TsaSigner},
 };
 
 mod info;
 mod tree;
 
+mod audit;
 mod callback_signer;
+mod claim_generator;
+mod extract;
+mod format;
+mod fragment;
+mod http_signer;
+mod keygen;
+mod kms;
+mod live;
+mod playlist;
+mod preflight;
+mod private_key;
+mod query;
+mod remote;
+mod sidecar;
 mod signer;
+mod summary;
+mod training;
+mod tsa;
 
 /// Tool for displaying and creating C2PA manifests.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None, arg_required_else_help(true))]
+#[command(group(ArgGroup::new("external_signer").args(["signer_path", "signer_url"])))]
 struct CliArgs {
     /// Path to manifest definition JSON file.
     #[clap(short, long, requires = "output")]
@@ -67,6 +100,15 @@ struct CliArgs {
     #[clap(short, long)]
     detailed: bool,
 
+    /// Format of the manifest, detailed and ingredient reports: json, canonical-json (with
+    /// sorted keys, for stable diffs), yaml or cbor.
+    #[clap(long, value_enum, default_value_t = ReportFormat::Json)]
+    format: ReportFormat,
+
+    /// Display a plain language summary of the active manifest instead of a report.
+    #[clap(long, conflicts_with_all = ["detailed", "ingredient", "format"])]
+    summary: bool,
+
     /// Force overwrite of output if it already exists.
     #[clap(short, long)]
     force: bool,
@@ -78,10 +120,25 @@ struct CliArgs {
     #[clap(short, long)]
     remote: Option<String>,
 
+    /// Publish the manifest store for the remote URL to a folder or an HTTP server (using PUT),
+    /// at the path of the remote URL. An existing store is only replaced with -f/--force.
+    #[clap(long, requires = "remote")]
+    publish: Option<StoreFolder>,
+
     /// Generate a sidecar (.c2pa) manifest
     #[clap(short, long)]
     sidecar: bool,
 
+    /// Path to a sidecar (.c2pa) manifest store to validate against the asset.
+    /// By default, a .c2pa file next to the asset is used if the asset has no manifest.
+    #[clap(long, conflicts_with_all = ["manifest", "config"])]
+    manifest_file: Option<PathBuf>,
+
+    /// Read remote manifests locally instead of fetching them, given as <URL_PREFIX>=<FOLDER_OR_URL>.
+    /// The rest of the remote URL after the prefix is appended to the folder or URL.
+    #[clap(long = "remote-map", value_name = "URL_PREFIX=FOLDER_OR_URL")]
+    remote_map: Vec<RemoteMapping>,
+
     /// Write ingredient report and assets to a folder.
     #[clap(short, long)]
     ingredient: bool,
@@ -105,31 +162,68 @@ struct CliArgs {
     #[clap(long)]
     info: bool,
 
+    /// Path to a JSON file of signing settings: alg, private_key, sign_cert, ta_url and signer.
+    /// Its settings take precedence over those in the manifest definition.
+    #[clap(long, env = "C2PATOOL_SIGNER_CONFIG")]
+    signer_config: Option<PathBuf>,
+
+    /// Name of a profile in the signer_config file to sign with.
+    #[clap(long, env = "C2PATOOL_SIGNER_PROFILE", requires = "signer_config")]
+    signer_profile: Option<String>,
+
+    /// Where to read the passphrase of an encrypted private_key or PKCS#12 file from.
+    #[command(flatten)]
+    key_passphrase: KeyPassphraseArgs,
+
     /// Path to an executable that will sign the claim bytes.
     #[clap(long)]
     signer_path: Option<PathBuf>,
 
-    /// To be used with the [callback_signer] argument. This value should at least: size of CoseSign1 CBOR +
-    /// the size of certificate chain provided in the manifest definition's `sign_cert` field + the size of the
-    /// signature of the Time Stamp Authority response. A typical size of CoseSign1 CBOR is in the 1-2K range. If
-    /// the reserve size is too small an error will be returned during signing.
-    /// For example:
-    ///
-    /// The reserve-size can be calculated like this if you aren't including a `tsa_url` key in
-    /// your manifest description:
-    ///
-    ///     1024 + sign_cert.len()
-    ///
-    /// Or, if you are including a `tsa_url` in your manifest definition, you will calculate the
-    /// reserve size like this:
-    ///
-    ///     1024 + sign_cert.len() + tsa_signature_response.len()
+    /// URL of an HTTP service that will sign the claim bytes. The claim bytes are POSTed
+    /// to it and the signature is read from the response.
+    #[clap(long)]
+    signer_url: Option<Url>,
+
+    /// How claim bytes and signatures are exchanged with the signer_path executable or
+    /// signer_url service. With json, a JSON request is sent and a JSON response is read back.
+    #[clap(long, value_enum, default_value_t = SignerProtocol::Raw, requires = "external_signer")]
+    signer_protocol: SignerProtocol,
+
+    /// Headers, authentication and TLS settings for the signer_url service.
+    #[command(flatten)]
+    signer_http: HttpSignerArgs,
+
+    /// Timeout, retries, arguments and environment for the signer_path executable.
+    #[command(flatten)]
+    signer_process: SignerProcessArgs,
+
+    /// To be used with the [callback_signer] argument. The size reserved for the COSE signature,
+    /// which holds the signature, the certificate chain provided in the manifest definition's
+    /// `sign_cert` field and, with a `ta_url`, the Time Stamp Authority response.
     ///
-    /// Note:
-    /// We'll default the `reserve-size` to a value of 20_000, if no value is provided. This
-    /// will probably leave extra `0`s of unused space. Please specify a reserve-size if possible.
-    #[clap(long, default_value("20000"))]
-    reserve_size: usize,
+    /// By default, the reserve size is computed as 1024 + the size of the certificate chain +
+    /// the size of a time stamp response, requesting one from the TSA once to measure it. If the
+    /// signature still doesn't fit, signing is retried with a larger reserve size.
+    /// If a reserve size is given, it is used as is and an error is returned if it is too small.
+    #[clap(long)]
+    reserve_size: Option<usize>,
+
+    /// Time stamp authorities to use, what to do when none responds, and how long to wait.
+    #[command(flatten)]
+    tsa: TsaArgs,
+
+    /// Append a JSON line recording each signature made to this file: the input and output
+    /// paths and hashes, manifest label, signer certificate, algorithm and time stamp authority.
+    #[clap(long, env = "C2PATOOL_AUDIT_LOG", value_name = "PATH")]
+    audit_log: Option<PathBuf>,
+
+    /// AI training and data mining preferences to add to the manifest.
+    #[command(flatten)]
+    training_mining: TrainingMiningArgs,
+
+    /// Name, version and icon of the application creating the manifest.
+    #[command(flatten)]
+    claim_generator: ClaimGeneratorArgs,
 }
 
 #[derive(Clone, Debug)]
@@ -151,7 +245,6 @@ fn parse_resource_string(s: &str) -> Result<TrustResource> {
 // We only construct one per invocation, not worth shrinking this.
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
-#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Sub-command to configure trust store options, "trust --help for more details"
     Trust {
@@ -167,6 +260,55 @@ enum Commands {
         #[arg(long = "trust_config", env="C2PATOOL_TRUST_CONFIG", value_parser = parse_resource_string)]
         trust_config: Option<TrustResource>,
     },
+    /// Sub-command to generate a test CA and signing certificates for development and testing
+    ///
+    /// The keys, certificates, trust anchors and trust config are written to the folder given as
+    /// the path, for example:
+    ///
+    /// c2patool test_certs keygen --alg es256,ps256
+    #[command(verbatim_doc_comment)]
+    Keygen {
+        /// Signing algorithms to generate keys and certificates for, separated by commas.
+        /// Defaults to all of them.
+        #[arg(long = "alg", value_delimiter = ',')]
+        algs: Vec<SigningAlg>,
+
+        /// Number of days the certificates are valid for.
+        #[arg(long, default_value_t = 365)]
+        days: u32,
+    },
+    /// Sub-command to extract an assertion, resource or the manifest store from an asset
+    ///
+    /// The box is given by a JUMBF URI, a URI relative to the active manifest or an
+    /// assertion label of the active manifest, and written to the --output file or stdout,
+    /// for example:
+    ///
+    /// c2patool image.jpg -o thumbnail.jpg extract "self#jumbf=/c2pa/urn:uuid:.../c2pa.assertions/c2pa.thumbnail.ingredient.jpeg"
+    ///
+    /// c2patool image.jpg -o actions.cbor extract c2pa.actions
+    ///
+    /// c2patool image.jpg -o image.c2pa extract "self#jumbf=/c2pa"
+    #[command(verbatim_doc_comment)]
+    Extract {
+        /// JUMBF URI or assertion label of the box to extract.
+        uri: String,
+    },
+    /// Sub-command to print the values a path expression selects from the manifest store
+    ///
+    /// Each value is printed as JSON on its own line, and the exit code is non-zero when
+    /// nothing matches. Ingredients with content credentials have their manifest nested as
+    /// "manifest", for example:
+    ///
+    /// c2patool image.jpg query 'active.assertions["c2pa.actions"].actions[*].action'
+    ///
+    /// c2patool image.jpg query 'active.ingredients[*].manifest.signature_info.issuer'
+    ///
+    /// c2patool image.jpg query '$..digitalSourceType'
+    #[command(verbatim_doc_comment)]
+    Query {
+        /// Path expression, like active.title, a subset of JSONPath.
+        expression: String,
+    },
     /// Sub-command to add manifest to fragmented BMFF content
     ///
     /// The init path can be a glob to process entire directories of content, for example:
@@ -174,6 +316,9 @@ enum Commands {
     /// c2patool -m test2.json -o /my_output_folder "/my_renditions/**/my_init.mp4" fragment --fragments_glob "myfile_abc*[0-9].m4s"
     ///
     /// Note: the glob patterns are quoted to prevent shell expansion.
+    ///
+    /// The path can also be an HLS (.m3u8) or DASH (.mpd) playlist, in which case the init segments
+    /// and fragments of every rendition are read from the playlist and no fragments_glob is needed.
     Fragment {
         /// Glob pattern to find the fragments of the asset. The path is automatically set to be the same as
         /// the init segment.
@@ -182,18 +327,44 @@ enum Commands {
         /// to match [myfile_abc1.m4s, myfile_abc2180.m4s, ...] )
         #[arg(long = "fragments_glob", verbatim_doc_comment)]
         fragments_glob: Option<PathBuf>,
+
+        /// Keep watching the init segment folders and sign new fragments as they appear.
+        #[arg(long, requires = "fragments_glob")]
+        watch: bool,
+
+        /// File used to save watch progress so signing can resume after a restart.
+        /// Defaults to .c2patool_checkpoint.json in the output folder.
+        #[arg(long, requires = "watch")]
+        checkpoint: Option<PathBuf>,
+
+        /// How often to look for new fragments when watching, in milliseconds.
+        #[arg(long = "poll_interval", default_value_t = 1000, requires = "watch")]
+        poll_interval: u64,
+
+        /// Stop watching after this many seconds without new fragments.
+        #[arg(long = "idle_timeout", requires = "watch")]
+        idle_timeout: Option<u64>,
     },
 }
 
 #[derive(Debug, Default, Deserialize)]
 // Add fields that are not part of the standard Manifest
 struct ManifestDef {
-    #[serde(flatten)]
-    manifest: ManifestDefinition,
     // allows adding ingredients with file paths
     ingredient_paths: Option<Vec<PathBuf>>,
 }
 
+// runs `sign`, again with a larger reserve size if the callback signer computes it and it was too small
+fn retry_reserve_size<T>(
+    callback_signer: Option<&CallbackSigner>,
+    mut sign: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    match callback_signer {
+        Some(signer) => signer.retry_reserve_size(sign),
+        None => sign(),
+    }
+}
+
 // convert certain errors to output messages
 fn special_errs(e: c2pa::Error) -> anyhow::Error {
     match e {
@@ -260,13 +431,29 @@ fn load_trust_resource(resource: &TrustResource) -> Result<String> {
     }
 }
 
-fn configure_sdk(args: &CliArgs) -> Result<()> {
+// the manifest store of an asset for a report, which only CBOR reports read assertions from
+fn report_store(
+    format: ReportFormat,
+    path: &Path,
+    manifest_file: Option<&Path>,
+    remote: &RemoteResolver,
+) -> Result<Vec<u8>> {
+    match format {
+        ReportFormat::Cbor => Ok(sidecar::locate(path, manifest_file, remote)?.0),
+        _ => Ok(Vec::new()),
+    }
+}
+
+// configures the SDK, returning whether signing certificates are checked against trust lists,
+// and the trust config if one was given, which signing certificates are checked against too
+fn configure_sdk(args: &CliArgs) -> Result<(bool, Option<String>)> {
     const TA: &str = r#"{"trust": { "trust_anchors": replacement_val } }"#;
     const AL: &str = r#"{"trust": { "allowed_list": replacement_val } }"#;
     const TC: &str = r#"{"trust": { "trust_config": replacement_val } }"#;
     const VS: &str = r#"{"verify": { "verify_after_sign": replacement_val } }"#;
 
     let mut enable_trust_checks = false;
+    let mut trust_config_data = None;
 
     if let Some(Commands::Trust {
         trust_anchors,
@@ -299,10 +486,11 @@ fn configure_sdk(args: &CliArgs) -> Result<()> {
         if let Some(trust_config) = &trust_config {
             let data = load_trust_resource(trust_config)?;
             debug!("Using trust config from {:?}", trust_config);
-            let replacement_val = serde_json::Value::String(data).to_string(); // escape string
+            let replacement_val = serde_json::Value::String(data.clone()).to_string(); // escape string
             let setting = TC.replace("replacement_val", &replacement_val);
 
             c2pa::settings::load_settings_from_str(&setting, "json")?;
+            trust_config_data = Some(data);
 
             enable_trust_checks = true;
         }
@@ -323,104 +511,7 @@ fn configure_sdk(args: &CliArgs) -> Result<()> {
         c2pa::settings::load_settings_from_str(&setting, "json")?;
     }
 
-    Ok(())
-}
-
-fn sign_fragmented(
-    builder: &mut Builder,
-    signer: &dyn Signer,
-    init_pattern: &Path,
-    frag_pattern: &PathBuf,
-    output_path: &Path,
-) -> Result<()> {
-    // search folders for init segments
-    let ip = init_pattern.to_str().ok_or(c2pa::Error::OtherError(
-        "could not parse source pattern".into(),
-    ))?;
-    let inits = glob::glob(ip).context("could not process glob pattern")?;
-    let mut count = 0;
-    for init in inits {
-        match init {
-            Ok(p) => {
-                let mut fragments = Vec::new();
-                let init_dir = p.parent().context("init segment had no parent dir")?;
-                let seg_glob = init_dir.join(frag_pattern); // segment match pattern
-
-                // grab the fragments that go with this init segment
-                let seg_glob_str = seg_glob.to_str().context("fragment path not valid")?;
-                let seg_paths = glob::glob(seg_glob_str).context("fragment glob not valid")?;
-                for seg in seg_paths {
-                    match seg {
-                        Ok(f) => fragments.push(f),
-                        Err(_) => return Err(anyhow!("fragment path not valid")),
-                    }
-                }
-
-                println!("Adding manifest to: {:?}", p);
-                let new_output_path =
-                    output_path.join(init_dir.file_name().context("invalid file name")?);
-                builder.sign_fragmented_files(signer, &p, &fragments, &new_output_path)?;
-
-                count += 1;
-            }
-            Err(_) => bail!("bad path to init segment"),
-        }
-    }
-    if count == 0 {
-        println!("No files matching pattern: {}", ip);
-    }
-    Ok(())
-}
-
-fn verify_fragmented(init_pattern: &Path, frag_pattern: &Path) -> Result<Vec<Reader>> {
-    let mut readers = Vec::new();
-
-    let ip = init_pattern
-        .to_str()
-        .context("could not parse source pattern")?;
-    let inits = glob::glob(ip).context("could not process glob pattern")?;
-    let mut count = 0;
-
-    // search folders for init segments
-    for init in inits {
-        match init {
-            Ok(p) => {
-                let mut fragments = Vec::new();
-                let init_dir = p.parent().context("init segment had no parent dir")?;
-                let seg_glob = init_dir.join(frag_pattern); // segment match pattern
-
-                // grab the fragments that go with this init segment
-                let seg_glob_str = seg_glob.to_str().context("fragment path not valid")?;
-                let seg_paths = glob::glob(seg_glob_str).context("fragment glob not valid")?;
-                for seg in seg_paths {
-                    match seg {
-                        Ok(f) => fragments.push(f),
-                        Err(_) => return Err(anyhow!("fragment path not valid")),
-                    }
-                }
-
-                println!("Verifying manifest: {:?}", p);
-                let reader = Reader::from_fragmented_files(p, &fragments)?;
-                if let Some(vs) = reader.validation_status() {
-                    if let Some(e) = vs.iter().find(|v| !v.passed()) {
-                        eprintln!("Error validating segments: {:?}", e);
-                        return Ok(readers);
-                    }
-                }
-
-                readers.push(reader);
-
-                count += 1;
-            }
-            Err(_) => bail!("bad path to init segment"),
-        }
-    }
-
-    if count == 0 {
-        println!("No files matching pattern: {}", ip);
-    }
-
-    Ok(readers)
+    Ok((enable_trust_checks, trust_config_data))
 }
 
 fn main() -> Result<()> {
@@ -433,13 +524,18 @@ fn main() -> Result<()> {
     env_logger::init();
 
     let path = &args.path;
+    let remote = RemoteResolver::new(args.remote_map.clone());
+
+    if let Some(Commands::Keygen { algs, days }) = &args.command {
+        return keygen::keygen(path, algs, *days, args.force);
+    }
 
     if args.info {
-        return info(path);
+        return info(path, &remote);
     }
 
     if args.cert_chain {
-        let reader = Reader::from_file(path).map_err(special_errs)?;
+        let (reader, _) = sidecar::load(path, args.manifest_file.as_deref(), &remote)?;
         if let Some(manifest) = reader.active_manifest() {
             if let Some(si) = manifest.signature_info() {
                 println!("{}", si.cert_chain());
@@ -455,10 +551,18 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let is_fragment = matches!(
-        &args.command,
-        Some(Commands::Fragment { fragments_glob: _ })
-    );
+    if let Some(Commands::Extract { uri }) = &args.command {
+        return extract::extract(
+            path,
+            uri,
+            args.output.as_deref(),
+            args.force,
+            args.manifest_file.as_deref(),
+            &remote,
+        );
+    }
+
+    let is_fragment = matches!(&args.command, Some(Commands::Fragment { .. }));
 
     // make sure path is not a glob when not fragmented
     if !args.path.is_file() && !is_fragment {
@@ -466,7 +570,24 @@ fn main() -> Result<()> {
     }
 
     // configure the SDK
-    configure_sdk(&args).context("Could not configure c2pa-rs")?;
+    let (trust_checked, trust_config) =
+        configure_sdk(&args).context("Could not configure c2pa-rs")?;
+    let trust_config = trust_config.as_deref();
+
+    if let Some(Commands::Query { expression }) = &args.command {
+        if args.format != ReportFormat::Json {
+            bail!("Queries print JSON values, other formats are not supported");
+        }
+        let (reader, _) = sidecar::load(path, args.manifest_file.as_deref(), &remote)?;
+        let values = query::query(&reader, expression)?;
+        if values.is_empty() {
+            bail!("Nothing matches {:?}", expression);
+        }
+        for value in values {
+            println!("{}", value);
+        }
+        return Ok(());
+    }
 
     // Remove manifest needs to also remove XMP provenance
     // if args.remove_manifest {
@@ -509,25 +630,26 @@ fn main() -> Result<()> {
         // read the manifest information
         let manifest_def: ManifestDef = serde_json::from_slice(json.as_bytes())?;
         let mut builder = Builder::from_json(&json)?;
-        let mut manifest = manifest_def.manifest;
-
-        // add claim_tool generator so we know this was created using this tool
-        let mut tool_generator = ClaimGeneratorInfo::new(env!("CARGO_PKG_NAME"));
-        tool_generator.set_version(env!("CARGO_PKG_VERSION"));
-        if !manifest.claim_generator_info.is_empty()
-            || manifest.claim_generator_info[0].name == "c2pa-rs"
-        {
-            manifest.claim_generator_info = vec![tool_generator];
-        } else {
-            manifest.claim_generator_info.insert(1, tool_generator);
-        }
-        println!("claim generator {:?}", manifest.claim_generator_info);
+
+        // merge any training and mining preferences given on the command line
+        args.training_mining.merge_into(&mut builder)?;
+
+        // add the application given on the command line and c2patool to the claim generators,
+        // so we know this was created using this tool
+        args.claim_generator.merge_into(&mut builder)?;
         // set manifest base path before ingredients so ingredients can override it
         if let Some(base) = base_path.as_ref() {
             builder.base_path = Some(base.clone());
             sign_config.set_base_path(base);
         }
 
+        // signing settings from a signer configuration take precedence over the manifest's
+        if let Some(signer_config) = &args.signer_config {
+            let profile = args.signer_profile.as_deref();
+            sign_config = SignConfig::from_file(signer_config, profile)?.or(sign_config);
+        }
+        sign_config = args.tsa.sign_config().or(sign_config);
+
         // Add any ingredients specified as file paths
         if let Some(paths) = manifest_def.ingredient_paths {
             for mut path in paths {
@@ -559,42 +681,157 @@ fn main() -> Result<()> {
             }
         }
 
-        if let Some(remote) = args.remote {
+        if let Some(remote_url) = &args.remote {
             if args.sidecar {
                 builder.set_no_embed(true);
-                builder.set_remote_url(remote);
+                builder.set_remote_url(remote_url);
             } else {
-                builder.set_remote_url(remote);
+                builder.set_remote_url(remote_url);
             }
         } else if args.sidecar {
             builder.set_no_embed(true);
         }
 
-        let signer = if let Some(signer_process_name) = args.signer_path {
-            let cb_config = CallbackSignerConfig::new(&sign_config, args.reserve_size)?;
-
-            let process_runner = Box::new(ExternalProcessRunner::new(
+        let signer_kind = if args.signer_path.is_some() {
+            "process"
+        } else if args.signer_url.is_some() {
+            "http"
+        } else if let Some(SignerDef::Kms(_)) = &sign_config.signer {
+            "kms"
+        } else {
+            "local"
+        };
+        let callback_signer = if let Some(signer_process_name) = args.signer_path {
+            let cb_config =
+                CallbackSignerConfig::new(&sign_config, args.reserve_size, trust_config)?;
+
+            let context = match args.signer_protocol {
+                SignerProtocol::Raw => SignContext::default(),
+                SignerProtocol::Json => SignContext {
+                    asset_path: Some(args.path.clone()),
+                },
+            };
+            let process_runner = Box::new(
+                ExternalProcessRunner::new(cb_config.clone(), signer_process_name)
+                    .with_protocol(args.signer_protocol, context)
+                    .with_process_args(args.signer_process.clone()),
+            );
+            Some(CallbackSigner::new(process_runner, cb_config))
+        } else if let Some(signer_url) = args.signer_url {
+            let cb_config =
+                CallbackSignerConfig::new(&sign_config, args.reserve_size, trust_config)?;
+
+            let context = match args.signer_protocol {
+                SignerProtocol::Raw => SignContext::default(),
+                SignerProtocol::Json => SignContext {
+                    asset_path: Some(args.path.clone()),
+                },
+            };
+            let http_signer = HttpSigner::new(
                 cb_config.clone(),
-                signer_process_name,
-            ));
-            let signer = CallbackSigner::new(process_runner, cb_config);
-
-            Box::new(signer)
+                signer_url,
+                &args.signer_http,
+                &args.signer_process,
+            )?
+            .with_protocol(args.signer_protocol, context);
+            Some(CallbackSigner::new(Box::new(http_signer), cb_config))
+        } else if let Some(SignerDef::Kms(kms)) = &sign_config.signer {
+            let cb_config =
+                CallbackSignerConfig::new(&sign_config, args.reserve_size, trust_config)?;
+            let callback = kms.callback(cb_config.alg)?;
+            Some(CallbackSigner::new(callback, cb_config))
         } else {
-            sign_config.signer()?
+            None
+        };
+        let local_signer;
+        let signer: &dyn Signer = match &callback_signer {
+            Some(signer) => signer,
+            None => {
+                local_signer = sign_config.signer(&args.key_passphrase, trust_config)?;
+                local_signer.as_ref()
+            }
         };
+        let callback_signer = callback_signer.as_ref();
+        let tsa_signer = TsaSigner::new(signer, &sign_config);
+        let signer: &dyn Signer = &tsa_signer;
+        let audit_log = args
+            .audit_log
+            .as_deref()
+            .map(|log| AuditLog::open(log, &tsa_signer, signer_kind))
+            .transpose()?;
+        let audit_log = audit_log.as_ref();
 
         if let Some(output) = args.output {
             // fragmented embedding
-            if let Some(Commands::Fragment { fragments_glob }) = &args.command {
+            if let Some(Commands::Fragment {
+                fragments_glob,
+                watch,
+                checkpoint,
+                poll_interval,
+                idle_timeout,
+            }) = &args.command
+            {
                 if output.exists() && !output.is_dir() {
                     bail!("Output cannot point to existing file, must be a directory");
                 }
 
-                if let Some(fg) = &fragments_glob {
-                    return sign_fragmented(&mut builder, signer.as_ref(), &args.path, fg, &output);
+                if let (true, Some(fg)) = (watch, &fragments_glob) {
+                    create_dir_all(&output)?;
+                    let options = live::WatchOptions {
+                        checkpoint: checkpoint
+                            .clone()
+                            .unwrap_or_else(|| output.join(".c2patool_checkpoint.json")),
+                        poll_interval: Duration::from_millis(*poll_interval),
+                        idle_timeout: idle_timeout.map(Duration::from_secs),
+                    };
+                    let result = retry_reserve_size(callback_signer, || {
+                        live::watch_fragmented(
+                            &mut builder,
+                            signer,
+                            path,
+                            fg,
+                            &output,
+                            &options,
+                            audit_log,
+                        )
+                    });
+                    tsa_signer.print_report();
+                    return result;
+                } else if let Some(fg) = &fragments_glob {
+                    let renditions = fragment::find_renditions(&args.path, fg)?;
+                    if renditions.is_empty() {
+                        eprintln!("No files matching pattern: {:?}", args.path);
+                    }
+                    let result = retry_reserve_size(callback_signer, || {
+                        fragment::sign_renditions(
+                            &mut builder,
+                            signer,
+                            &renditions,
+                            &output,
+                            audit_log,
+                        )
+                    });
+                    tsa_signer.print_report();
+                    return result;
+                } else if playlist::is_playlist(&args.path) {
+                    let playlist = Playlist::load(&args.path)?;
+                    // rewrite the playlists first, so only writing them can fail after signing
+                    let playlists = playlist.rewritten()?;
+                    playlist::check_playlists(&output, &playlists, args.force)?;
+                    let result = retry_reserve_size(callback_signer, || {
+                        fragment::sign_renditions(
+                            &mut builder,
+                            signer,
+                            &playlist.renditions,
+                            &output,
+                            audit_log,
+                        )
+                    });
+                    tsa_signer.print_report();
+                    result?;
+                    return playlist::write_playlists(&output, &playlists);
                 } else {
-                    bail!("fragments_glob must be set");
+                    bail!("fragments_glob must be set unless the path is an HLS or DASH playlist");
                 }
             } else {
                 if ext_normal(&output) != ext_normal(&args.path) {
@@ -611,23 +848,75 @@ fn main() -> Result<()> {
                     bail!("Missing extension output");
                 }
 
-                #[allow(deprecated)] // todo: remove when we can
-                builder
-                    .sign_file(signer.as_ref(), &args.path, &output)
-                    .context("embedding manifest")?;
+                // a failed attempt leaves the output behind, and sign_file refuses to write
+                // to an existing file, so remove it before trying again
+                let mut attempted = false;
+                let manifest_data = retry_reserve_size(callback_signer, || {
+                    if std::mem::replace(&mut attempted, true) {
+                        std::fs::remove_file(&output).ok();
+                    }
+                    #[allow(deprecated)] // todo: remove when we can
+                    let manifest_data = builder.sign_file(signer, path, &output)?;
+                    Ok(manifest_data)
+                })
+                .context("embedding manifest")?;
+                tsa_signer.print_report();
+
+                // the manifest store is not embedded, so write it next to the output
+                let sidecar = args.sidecar.then(|| sidecar::sidecar_path(&output));
+                if let Some(sidecar) = &sidecar {
+                    std::fs::write(sidecar, &manifest_data)
+                        .context(format!("writing sidecar manifest {:?}", sidecar))?;
+                }
+
+                // record the signing before anything else can fail
+                if let Some(audit_log) = audit_log {
+                    let flow = match (&args.remote, &sidecar) {
+                        (Some(url), sidecar) => Flow::Remote {
+                            url,
+                            sidecar: sidecar.as_deref(),
+                        },
+                        (None, Some(sidecar)) => Flow::Sidecar(sidecar),
+                        (None, None) => Flow::Embedded,
+                    };
+                    let reader = sidecar::validate(&output, &manifest_data).ok();
+                    audit_log.record(flow, path, &output, reader.as_ref())?;
+                }
+
+                if let (Some(target), Some(remote_url)) = (&args.publish, &args.remote) {
+                    let location = remote::publish(&manifest_data, remote_url, target, args.force)?;
+                    eprintln!("Published manifest store to {}", location);
+                    eprintln!("Remote manifest URL: {}", remote_url);
+                }
 
                 // generate a report on the output file
-                let reader = Reader::from_file(&output).map_err(special_errs)?;
-                if args.detailed {
-                    println!("{:#?}", reader);
+                let (reader, binding) = sidecar::load(&output, sidecar.as_deref(), &remote)?;
+                if args.summary {
+                    print!("{}", summary::summary(&reader, trust_checked));
+                } else if args.detailed {
+                    args.format.print(&format::reader_report(&reader, true)?)?;
                 } else {
-                    println!("{}", reader)
+                    args.format
+                        .print_store(&format::reader_report(&reader, false)?, &manifest_data)?;
+                }
+                if !args.detailed {
+                    if let Some(report) = training::report(&reader) {
+                        eprint!("{}", report);
+                    }
+                }
+                if binding != sidecar::Binding::Embedded {
+                    eprintln!("{binding}");
                 }
             }
         } else {
             bail!("Output path required with manifest definition")
         }
-    } else if args.parent.is_some() || args.sidecar || args.remote.is_some() {
+    } else if args.parent.is_some()
+        || args.sidecar
+        || args.remote.is_some()
+        || !args.training_mining.is_empty()
+        || !args.claim_generator.is_empty()
+    {
         bail!("Manifest definition required with these options or flags")
     } else if let Some(output) = args.output {
         if output.is_file() || output.extension().is_some() {
@@ -641,50 +930,97 @@ fn main() -> Result<()> {
             }
         }
         create_dir_all(&output)?;
+        let extension = args.format.extension();
         if args.ingredient {
-            let report = Ingredient::from_file_with_folder(&args.path, &output)
-                .map_err(special_errs)?
-                .to_string();
-            File::create(output.join("ingredient.json"))?.write_all(&report.into_bytes())?;
+            let ingredient =
+                Ingredient::from_file_with_folder(&args.path, &output).map_err(special_errs)?;
+            let report = args
+                .format
+                .encode(&format::ingredient_report(&ingredient)?)?;
+            File::create(output.join(format!("ingredient.{extension}")))?.write_all(&report)?;
             println!("Ingredient report written to the directory {:?}", &output);
         } else {
-            let reader = Reader::from_file(&args.path).map_err(special_errs)?;
+            let manifest_file = args.manifest_file.as_deref();
+            let (reader, _) = sidecar::load(&args.path, manifest_file, &remote)?;
             reader.to_folder(&output)?;
-            let report = reader.to_string();
+            let store = report_store(args.format, &args.path, manifest_file, &remote)?;
+            let report = args
+                .format
+                .encode_store(&format::reader_report(&reader, false)?, &store)?;
             if args.detailed {
                 // for a detailed report first call the above to generate the thumbnails
                 // then call this to add the detailed report
-                let detailed = format!(
-                    "{:#?}",
-                    Reader::from_file(&args.path).map_err(special_errs)?
-                );
-                File::create(output.join("detailed.json"))?.write_all(&detailed.into_bytes())?;
+                let reader = sidecar::load(&args.path, manifest_file, &remote)?.0;
+                let detailed = args.format.encode(&format::reader_report(&reader, true)?)?;
+                File::create(output.join(format!("detailed.{extension}")))?.write_all(&detailed)?;
             }
-            File::create(output.join("manifest_store.json"))?.write_all(&report.into_bytes())?;
+            File::create(output.join(format!("manifest_store.{extension}")))?.write_all(&report)?;
             println!("Manifest report written to the directory {:?}", &output);
         }
     } else if args.ingredient {
-        println!(
-            "{}",
-            Ingredient::from_file(&args.path).map_err(special_errs)?
-        )
+        let ingredient = Ingredient::from_file(&args.path).map_err(special_errs)?;
+        args.format
+            .print(&format::ingredient_report(&ingredient)?)?;
     } else if args.detailed {
-        println!(
-            "{:#?}",
-            Reader::from_file(&args.path).map_err(special_errs)?
-        )
+        let (reader, binding) = sidecar::load(&args.path, args.manifest_file.as_deref(), &remote)?;
+        args.format.print(&format::reader_report(&reader, true)?)?;
+        if binding != sidecar::Binding::Embedded {
+            eprintln!("{binding}");
+        }
     } else if let Some(Commands::Fragment {
-        fragments_glob: Some(fg),
+        fragments_glob,
+        watch,
+        ..
     }) = &args.command
     {
-        let stores = verify_fragmented(&args.path, fg)?;
-        if stores.len() == 1 {
-            println!("{}", stores[0]);
+        if *watch {
+            bail!("--watch requires a manifest definition and an output folder");
+        }
+        let renditions = if let Some(fg) = fragments_glob {
+            fragment::find_renditions(&args.path, fg)?
+        } else if playlist::is_playlist(&args.path) {
+            Playlist::load(&args.path)?.renditions
         } else {
-            println!("{} Init manifests validated", stores.len());
+            bail!("fragments_glob must be set unless the path is an HLS or DASH playlist");
+        };
+        if renditions.is_empty() {
+            bail!("No init segments found for {:?}", args.path);
+        }
+        let results = fragment::verify_renditions(&renditions)?;
+        let failed = results.iter().filter(|r| !r.passed()).count();
+        if failed > 0 {
+            bail!(
+                "{} of {} init manifests failed validation",
+                failed,
+                results.len()
+            );
+        }
+        match results.as_slice() {
+            [result] => {
+                if let Some(reader) = &result.reader {
+                    let store = report_store(args.format, &result.init, None, &remote)?;
+                    args.format
+                        .print_store(&format::reader_report(reader, false)?, &store)?;
+                }
+            }
+            _ => println!("{} Init manifests validated", results.len()),
         }
     } else {
-        println!("{}", Reader::from_file(&args.path).map_err(special_errs)?)
+        let (reader, binding) = sidecar::load(&args.path, args.manifest_file.as_deref(), &remote)?;
+        if args.summary {
+            print!("{}", summary::summary(&reader, trust_checked));
+        } else {
+            let manifest_file = args.manifest_file.as_deref();
+            let store = report_store(args.format, &args.path, manifest_file, &remote)?;
+            args.format
+                .print_store(&format::reader_report(&reader, false)?, &store)?;
+        }
+        if let Some(report) = training::report(&reader) {
+            eprint!("{}", report);
+        }
+        if binding != sidecar::Binding::Embedded {
+            eprintln!("{binding}");
+        }
     }
 
     Ok(())
@@ -720,7 +1056,7 @@ pub mod tests {
         let signer = SignConfig::from_json(CONFIG)
             .unwrap()
             .set_base_path("sample")
-            .signer()
+            .signer(&KeyPassphraseArgs::default(), None)
             .expect("get_signer");
 
         #[allow(deprecated)] // todo: remove when we can
@@ -728,7 +1064,7 @@ pub mod tests {
             .sign_file(signer.as_ref(), SOURCE_PATH, OUTPUT_PATH)
             .expect("embed");
 
-        let ms = Reader::from_file(OUTPUT_PATH)
+        let ms = c2pa::Reader::from_file(OUTPUT_PATH)
             .expect("from_file")
             .to_string();
         println!("{}", ms);
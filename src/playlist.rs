@@ -0,0 +1,687 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{create_dir_all, read_to_string, write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use roxmltree::{Document, Node};
+
+use crate::fragment::Rendition;
+
+/// Returns true if the path names an HLS or DASH playlist.
+pub fn is_playlist(path: &Path) -> bool {
+    playlist_format(path).is_some()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Hls,
+    Dash,
+}
+
+fn playlist_format(path: &Path) -> Option<Format> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "m3u8" => Some(Format::Hls),
+        "mpd" => Some(Format::Dash),
+        _ => None,
+    }
+}
+
+/// A streaming playlist and the renditions it references.
+///
+/// All output paths are relative to the folder of the top level playlist, so the
+/// output tree mirrors the layout of the source.
+#[derive(Debug)]
+pub struct Playlist {
+    format: Format,
+    root: PathBuf,
+    /// Every playlist file, relative to the root.
+    playlists: Vec<PathBuf>,
+    /// Maps each referenced source file to its location in the output tree.
+    outputs: HashMap<PathBuf, PathBuf>,
+    pub renditions: Vec<Rendition>,
+}
+
+impl Playlist {
+    /// Loads a playlist and resolves the init segments and fragments of every rendition.
+    pub fn load(path: &Path) -> Result<Self> {
+        let format = playlist_format(path).context("not an HLS or DASH playlist")?;
+        let path = normalize(&std::fs::canonicalize(path)?);
+        let root = path.parent().context("playlist has no parent folder")?;
+        let mut playlist = Playlist {
+            format,
+            root: root.to_path_buf(),
+            playlists: Vec::new(),
+            outputs: HashMap::new(),
+            renditions: Vec::new(),
+        };
+        match format {
+            Format::Hls => playlist.load_hls(&path)?,
+            Format::Dash => playlist.load_dash(&path)?,
+        }
+        if playlist.renditions.is_empty() {
+            bail!("no renditions found in playlist {:?}", path);
+        }
+        playlist.map_outputs()?;
+        Ok(playlist)
+    }
+
+    // path of a source file relative to the playlist root
+    fn relative(&self, path: &Path) -> Result<PathBuf> {
+        path.strip_prefix(&self.root)
+            .map(Path::to_path_buf)
+            .map_err(|_| anyhow::anyhow!("{:?} is outside the playlist folder", path))
+    }
+
+    fn add_rendition(&mut self, init: PathBuf, fragments: Vec<PathBuf>) -> Result<()> {
+        if fragments.is_empty() {
+            bail!("no segments found for init segment {:?}", init);
+        }
+        for file in std::iter::once(&init).chain(fragments.iter()) {
+            if !file.is_file() {
+                bail!("segment not found: {:?}", file);
+            }
+        }
+        if let Some(existing) = self.renditions.iter().find(|r| r.init == init) {
+            if existing.fragments != fragments {
+                bail!("init segment {:?} is used by more than one rendition", init);
+            }
+            return Ok(());
+        }
+        let output_dir = self
+            .relative(&init)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        self.renditions.push(Rendition {
+            init,
+            fragments,
+            output_dir,
+        });
+        Ok(())
+    }
+
+    // works out where every signed file lands and checks nothing collides
+    fn map_outputs(&mut self) -> Result<()> {
+        let mut outputs = HashMap::new();
+        for playlist in &self.playlists {
+            outputs.insert(self.root.join(playlist), playlist.clone());
+        }
+        for rendition in &self.renditions {
+            for file in std::iter::once(&rendition.init).chain(rendition.fragments.iter()) {
+                let name = file.file_name().context("invalid file name")?;
+                let output = rendition.output_dir.join(name);
+                if self.format == Format::Dash && self.relative(file)? != output {
+                    bail!(
+                        "DASH segment {:?} must be in the same folder as its init segment",
+                        file
+                    );
+                }
+                outputs.insert(file.clone(), output);
+            }
+        }
+        let mut seen = HashSet::new();
+        for output in outputs.values() {
+            if !seen.insert(output) {
+                bail!("more than one file would be written to {:?}", output);
+            }
+        }
+        self.outputs = outputs;
+        Ok(())
+    }
+
+    /// Returns the path of each playlist relative to the output folder, with its text
+    /// rewritten to point at the signed segments.
+    pub fn rewritten(&self) -> Result<Vec<(PathBuf, String)>> {
+        let mut rewritten = Vec::new();
+        for playlist in &self.playlists {
+            let source = self.root.join(playlist);
+            let text = read_to_string(&source)?;
+            let text = match self.format {
+                Format::Hls => self.rewrite_hls(&source, playlist, &text)?,
+                // DASH segments keep their layout, so the addressing is unchanged
+                Format::Dash => text,
+            };
+            rewritten.push((playlist.clone(), text));
+        }
+        Ok(rewritten)
+    }
+
+    fn load_hls(&mut self, path: &Path) -> Result<()> {
+        let relative = self.relative(path)?;
+        if self.playlists.contains(&relative) {
+            return Ok(());
+        }
+        self.playlists.push(relative);
+
+        let text = read_to_string(path).context(format!("reading playlist {:?}", path))?;
+        let base = path.parent().unwrap_or(&self.root).to_path_buf();
+        let is_master = text
+            .lines()
+            .any(|l| l.starts_with("#EXT-X-STREAM-INF") || l.starts_with("#EXT-X-MEDIA:"));
+
+        if is_master {
+            let mut variants = Vec::new();
+            let mut expect_variant = false;
+            for line in text.lines().map(str::trim) {
+                if line.starts_with("#EXT-X-I-FRAME-STREAM-INF") {
+                    bail!("I-frame playlists are not supported");
+                } else if line.starts_with("#EXT-X-STREAM-INF") {
+                    expect_variant = true;
+                } else if line.starts_with("#EXT-X-MEDIA:") {
+                    if let Some(uri) = hls_attribute(line, "URI") {
+                        variants.push(resolve(&base, uri)?);
+                    }
+                } else if expect_variant && !line.is_empty() && !line.starts_with('#') {
+                    variants.push(resolve(&base, line)?);
+                    expect_variant = false;
+                }
+            }
+            for variant in variants {
+                self.load_hls(&variant)?;
+            }
+            return Ok(());
+        }
+
+        // a media playlist, where each EXT-X-MAP starts a new init segment
+        let mut current: Option<(PathBuf, Vec<PathBuf>)> = None;
+        for line in text.lines().map(str::trim) {
+            if line.starts_with("#EXT-X-BYTERANGE") {
+                bail!("byte range segments are not supported");
+            } else if line.starts_with("#EXT-X-MAP") {
+                let uri = hls_attribute(line, "URI").context("EXT-X-MAP without URI")?;
+                if let Some((init, fragments)) = current.take() {
+                    self.add_rendition(init, fragments)?;
+                }
+                current = Some((resolve(&base, uri)?, Vec::new()));
+            } else if !line.is_empty() && !line.starts_with('#') {
+                match current.as_mut() {
+                    Some((_, fragments)) => fragments.push(resolve(&base, line)?),
+                    None => bail!(
+                        "media playlist {:?} has segments without an EXT-X-MAP init segment",
+                        path
+                    ),
+                }
+            }
+        }
+        if let Some((init, fragments)) = current {
+            self.add_rendition(init, fragments)?;
+        }
+        Ok(())
+    }
+
+    fn rewrite_hls(&self, source: &Path, playlist: &Path, text: &str) -> Result<String> {
+        let base = source.parent().unwrap_or(&self.root);
+        let from_dir = playlist.parent().unwrap_or_else(|| Path::new(""));
+        let map_uri = |uri: &str| -> Result<String> {
+            let target = resolve(base, uri)?;
+            Ok(match self.outputs.get(&target) {
+                Some(output) => relative_uri(from_dir, output),
+                None => uri.to_string(),
+            })
+        };
+
+        let mut out = String::with_capacity(text.len());
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("#EXT-X-MAP") || trimmed.starts_with("#EXT-X-MEDIA:") {
+                match hls_attribute(trimmed, "URI") {
+                    Some(uri) => {
+                        let quoted = format!("URI=\"{}\"", uri);
+                        out.push_str(
+                            &trimmed.replace(&quoted, &format!("URI=\"{}\"", map_uri(uri)?)),
+                        );
+                    }
+                    None => out.push_str(line),
+                }
+            } else if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                out.push_str(&map_uri(trimmed)?);
+            } else {
+                out.push_str(line);
+            }
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    fn load_dash(&mut self, path: &Path) -> Result<()> {
+        self.playlists.push(self.relative(path)?);
+        let text = read_to_string(path).context(format!("reading playlist {:?}", path))?;
+        let doc = Document::parse(&text).context("invalid DASH manifest")?;
+        let mpd = doc.root_element();
+        if mpd.attribute("type") == Some("dynamic") {
+            bail!("dynamic (live) DASH manifests are not supported");
+        }
+        let mpd_duration = mpd
+            .attribute("mediaPresentationDuration")
+            .map(parse_duration)
+            .transpose()?;
+        let mpd_base = with_base_url(path.parent().unwrap_or(&self.root), mpd)?;
+
+        for period in children(mpd, "Period") {
+            let period_duration = match period.attribute("duration") {
+                Some(d) => Some(parse_duration(d)?),
+                None => mpd_duration,
+            };
+            let period_base = with_base_url(&mpd_base, period)?;
+            for set in children(period, "AdaptationSet") {
+                let set_base = with_base_url(&period_base, set)?;
+                for rep in children(set, "Representation") {
+                    let base = with_base_url(&set_base, rep)?;
+                    let (init, media) = if let Some(list) = [rep, set]
+                        .iter()
+                        .find_map(|n| children(*n, "SegmentList").next())
+                    {
+                        segment_list(list)?
+                    } else {
+                        let template = [period, set, rep]
+                            .iter()
+                            .filter_map(|n| children(*n, "SegmentTemplate").next())
+                            .collect::<Vec<_>>();
+                        if template.is_empty() {
+                            bail!("only SegmentTemplate and SegmentList addressing is supported");
+                        }
+                        segment_template(&template, rep, period_duration)?
+                    };
+                    let init = resolve(&base, &init)?;
+                    let fragments = media
+                        .iter()
+                        .map(|m| resolve(&base, m))
+                        .collect::<Result<Vec<_>>>()?;
+                    self.add_rendition(init, fragments)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that none of the `playlists` already exists in the `output` folder, unless `force`
+/// is set.
+pub fn check_playlists(output: &Path, playlists: &[(PathBuf, String)], force: bool) -> Result<()> {
+    if force {
+        return Ok(());
+    }
+    for (playlist, _) in playlists {
+        let dest = output.join(playlist);
+        if dest.exists() {
+            bail!(
+                "Playlist {:?} already exists, use -f/force to force write",
+                dest
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Writes playlists returned by [Playlist::rewritten] to the output folder.
+pub fn write_playlists(output: &Path, playlists: &[(PathBuf, String)]) -> Result<()> {
+    for (playlist, text) in playlists {
+        let dest = output.join(playlist);
+        if let Some(parent) = dest.parent() {
+            create_dir_all(parent)?;
+        }
+        println!("Writing playlist: {:?}", dest);
+        write(&dest, text)?;
+    }
+    Ok(())
+}
+
+// returns the value of an attribute in an HLS tag such as `#EXT-X-MAP:URI="init.mp4"`
+fn hls_attribute<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let (_, attrs) = line.split_once(':')?;
+    let key = format!("{}=\"", name);
+    let mut rest = attrs;
+    while let Some(pos) = rest.find(&key) {
+        // make sure we matched a whole attribute name
+        if pos == 0 || rest[..pos].ends_with(',') {
+            let value = &rest[pos + key.len()..];
+            return value.split('"').next();
+        }
+        rest = &rest[pos + key.len()..];
+    }
+    None
+}
+
+// resolves a relative segment URI against a folder
+fn resolve(base: &Path, uri: &str) -> Result<PathBuf> {
+    if uri.contains("://") {
+        bail!("remote segment URIs are not supported: {}", uri);
+    }
+    let uri = uri.split(['?', '#']).next().unwrap_or_default();
+    Ok(normalize(&base.join(uri)))
+}
+
+// removes `.` and `..` components without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+// a URI for `to` relative to the folder `from`, both relative to the same root
+fn relative_uri(from: &Path, to: &Path) -> String {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts = vec!["..".to_string(); from.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |n| n.has_tag_name(name))
+}
+
+fn with_base_url(base: &Path, node: Node) -> Result<PathBuf> {
+    match children(node, "BaseURL").next().and_then(|n| n.text()) {
+        Some(url) => {
+            let url = url.trim();
+            if url.contains("://") {
+                bail!("remote BaseURL is not supported: {}", url);
+            }
+            Ok(normalize(&base.join(url)))
+        }
+        None => Ok(base.to_path_buf()),
+    }
+}
+
+fn segment_list(list: Node) -> Result<(String, Vec<String>)> {
+    let init = children(list, "Initialization")
+        .next()
+        .and_then(|n| n.attribute("sourceURL"))
+        .context("SegmentList without Initialization sourceURL")?;
+    let media = children(list, "SegmentURL")
+        .map(|n| n.attribute("media").map(str::to_string))
+        .collect::<Option<Vec<_>>>()
+        .context("SegmentURL without media")?;
+    Ok((init.to_string(), media))
+}
+
+// expands a SegmentTemplate, where `templates` are ordered from least to most specific
+fn segment_template(
+    templates: &[Node],
+    rep: Node,
+    period_duration: Option<f64>,
+) -> Result<(String, Vec<String>)> {
+    let attr = |name: &str| templates.iter().rev().find_map(|t| t.attribute(name));
+    let number_attr = |name: &str, default: u64| -> Result<u64> {
+        attr(name)
+            .map(|v| {
+                v.parse()
+                    .context(format!("invalid SegmentTemplate {}", name))
+            })
+            .unwrap_or(Ok(default))
+    };
+
+    let id = rep.attribute("id").unwrap_or_default();
+    let bandwidth = rep.attribute("bandwidth").unwrap_or_default();
+    let init_template = attr("initialization").context("SegmentTemplate without initialization")?;
+    let media_template = attr("media").context("SegmentTemplate without media")?;
+    let start_number = number_attr("startNumber", 1)?;
+    let timescale = number_attr("timescale", 1)?;
+
+    // (number, time) of each segment
+    let mut segments = Vec::new();
+    if let Some(timeline) = templates
+        .iter()
+        .rev()
+        .find_map(|t| children(*t, "SegmentTimeline").next())
+    {
+        let end = period_duration.map(|d| (d * timescale as f64).round() as u64);
+        let mut time = 0u64;
+        for s in children(timeline, "S") {
+            if let Some(t) = s.attribute("t") {
+                time = t.parse().context("invalid S@t")?;
+            }
+            let d: u64 = s
+                .attribute("d")
+                .context("S without d")?
+                .parse()
+                .context("invalid S@d")?;
+            let repeat: i64 = s
+                .attribute("r")
+                .unwrap_or("0")
+                .parse()
+                .context("invalid S@r")?;
+            let count = if repeat < 0 {
+                let end = end.context("open ended SegmentTimeline needs a duration")?;
+                end.saturating_sub(time).div_ceil(d)
+            } else {
+                repeat as u64 + 1
+            };
+            for _ in 0..count {
+                segments.push((start_number + segments.len() as u64, time));
+                time += d;
+            }
+        }
+    } else {
+        let duration = number_attr("duration", 0)?;
+        if duration == 0 {
+            bail!("SegmentTemplate needs a duration or a SegmentTimeline");
+        }
+        let total = period_duration.context("MPD has no mediaPresentationDuration")?;
+        let count = (total * timescale as f64 / duration as f64).ceil() as u64;
+        for i in 0..count {
+            segments.push((start_number + i, i * duration));
+        }
+    }
+
+    let init = expand_template(init_template, id, bandwidth, 0, 0)?;
+    let media = segments
+        .iter()
+        .map(|(number, time)| expand_template(media_template, id, bandwidth, *number, *time))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((init, media))
+}
+
+// substitutes the $identifier$ placeholders of a DASH template
+fn expand_template(
+    template: &str,
+    id: &str,
+    bandwidth: &str,
+    number: u64,
+    time: u64,
+) -> Result<String> {
+    let mut out = String::new();
+    let mut parts = template.split('$');
+    out.push_str(parts.next().unwrap_or_default());
+    while let Some(ident) = parts.next() {
+        let literal = parts
+            .next()
+            .context(format!("unterminated identifier in template {}", template))?;
+        let (name, width) = match ident.split_once("%0") {
+            Some((name, fmt)) => {
+                let width = fmt
+                    .strip_suffix('d')
+                    .and_then(|w| w.parse::<usize>().ok())
+                    .context(format!("invalid format in template {}", template))?;
+                (name, width)
+            }
+            None => (ident, 0),
+        };
+        match name {
+            "" => out.push('$'),
+            "RepresentationID" => out.push_str(id),
+            "Bandwidth" => out.push_str(bandwidth),
+            "Number" => out.push_str(&format!("{:0width$}", number, width = width)),
+            "Time" => out.push_str(&format!("{:0width$}", time, width = width)),
+            _ => bail!("unsupported identifier ${}$ in template {}", name, template),
+        }
+        out.push_str(literal);
+    }
+    Ok(out)
+}
+
+// parses an ISO 8601 duration such as PT1M30.5S into seconds
+fn parse_duration(s: &str) -> Result<f64> {
+    let rest = s
+        .strip_prefix('P')
+        .context(format!("invalid duration {}", s))?;
+    let mut seconds = 0.0;
+    let mut in_time = false;
+    let mut number = String::new();
+    for c in rest.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' | '.' => number.push(c),
+            _ => {
+                let value: f64 = number.parse().context(format!("invalid duration {}", s))?;
+                number.clear();
+                seconds += value
+                    * match (c, in_time) {
+                        ('D', false) => 86400.0,
+                        ('H', true) => 3600.0,
+                        ('M', true) => 60.0,
+                        ('S', true) => 1.0,
+                        _ => bail!("unsupported duration {}", s),
+                    };
+            }
+        }
+    }
+    Ok(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/fragmented")
+            .join(name)
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("PT3S").unwrap(), 3.0);
+        assert_eq!(parse_duration("PT1M30.5S").unwrap(), 90.5);
+        assert_eq!(parse_duration("P1DT1H").unwrap(), 90000.0);
+        assert!(parse_duration("3S").is_err());
+    }
+
+    #[test]
+    fn test_expand_template() {
+        assert_eq!(
+            expand_template("$RepresentationID$/seg-$Number%05d$.m4s", "v1", "", 7, 0).unwrap(),
+            "v1/seg-00007.m4s"
+        );
+        assert_eq!(
+            expand_template("t$Time$_$$.m4s", "", "", 1, 9000).unwrap(),
+            "t9000_$.m4s"
+        );
+        assert!(expand_template("$Unknown$", "", "", 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_segment_timeline() {
+        let xml = r#"<MPD><Period><AdaptationSet>
+            <SegmentTemplate initialization="init.mp4" media="$Time$.m4s" timescale="10">
+              <SegmentTimeline><S t="0" d="10" r="1"/><S d="5" r="-1"/></SegmentTimeline>
+            </SegmentTemplate>
+            <Representation id="a"/></AdaptationSet></Period></MPD>"#;
+        let doc = Document::parse(xml).unwrap();
+        let set = doc
+            .descendants()
+            .find(|n| n.has_tag_name("AdaptationSet"))
+            .unwrap();
+        let rep = children(set, "Representation").next().unwrap();
+        let template: Vec<_> = children(set, "SegmentTemplate").collect();
+        let (init, media) = segment_template(&template, rep, Some(3.0)).unwrap();
+        assert_eq!(init, "init.mp4");
+        assert_eq!(media, vec!["0.m4s", "10.m4s", "20.m4s", "25.m4s"]);
+    }
+
+    #[test]
+    fn test_hls_attribute() {
+        let line = r#"#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aud",URI="audio/a.m3u8""#;
+        assert_eq!(hls_attribute(line, "URI"), Some("audio/a.m3u8"));
+        assert_eq!(hls_attribute(line, "ID"), None);
+    }
+
+    #[test]
+    fn test_relative_uri() {
+        assert_eq!(
+            relative_uri(Path::new("video"), Path::new("video/seg-1.m4s")),
+            "seg-1.m4s"
+        );
+        assert_eq!(
+            relative_uri(Path::new("a/b"), Path::new("c/init.mp4")),
+            "../../c/init.mp4"
+        );
+    }
+
+    #[test]
+    fn test_load_hls() {
+        let playlist = Playlist::load(&fixture("master.m3u8")).unwrap();
+        assert_eq!(playlist.renditions.len(), 1);
+        let rendition = &playlist.renditions[0];
+        assert!(rendition.init.ends_with("video/init.mp4"));
+        assert_eq!(rendition.fragments.len(), 3);
+        assert_eq!(rendition.output_dir, PathBuf::from("video"));
+    }
+
+    #[test]
+    fn test_rewrite_hls_absolute_uris() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = std::fs::canonicalize(tempdir.path()).unwrap();
+        let media = root.join("media");
+        create_dir_all(&media).unwrap();
+        for name in ["init.mp4", "seg-1.m4s", "seg-2.m4s"] {
+            std::fs::copy(fixture("video").join(name), media.join(name)).unwrap();
+        }
+        let text = format!(
+            "#EXTM3U\n#EXT-X-MAP:URI=\"{0}/init.mp4\"\n#EXTINF:1,\n{0}/seg-1.m4s\n#EXTINF:1,\n{0}/seg-2.m4s\n",
+            media.display()
+        );
+        let source = root.join("abs.m3u8");
+        write(&source, &text).unwrap();
+
+        let playlist = Playlist::load(&source).unwrap();
+        let rewritten = playlist
+            .rewrite_hls(&source, Path::new("abs.m3u8"), &text)
+            .unwrap();
+        assert!(rewritten.contains("#EXT-X-MAP:URI=\"media/init.mp4\""));
+        assert!(rewritten.contains("\nmedia/seg-2.m4s\n"));
+    }
+
+    #[test]
+    fn test_load_dash() {
+        let playlist = Playlist::load(&fixture("manifest.mpd")).unwrap();
+        assert_eq!(playlist.renditions.len(), 1);
+        let rendition = &playlist.renditions[0];
+        assert!(rendition.init.ends_with("video/init.mp4"));
+        assert!(rendition.fragments[2].ends_with("video/seg-3.m4s"));
+    }
+}
@@ -0,0 +1,478 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+//! Checks run on the signing certificate and private key before anything is signed,
+//! so that problems are reported specifically instead of as a failed signature or a
+//! manifest that doesn't validate.
+
+use anyhow::{bail, Context, Result};
+use c2pa::SigningAlg;
+use openssl::{
+    asn1::Asn1Time,
+    nid::Nid,
+    pkey::{Id, PKey, PKeyRef, Public},
+    x509::{X509Ref, X509VerifyResult, X509},
+};
+use x509_parser::{certificate::X509Certificate, prelude::FromDer};
+
+/// The extended key usages c2pa accepts by default, as in its default trust config. This is
+/// also the trust config written by `keygen`.
+pub(crate) const DEFAULT_TRUST_CONFIG: &str = "\
+//id-kp-emailProtection
+1.3.6.1.5.5.7.3.4
+//id-kp-documentSigning
+1.3.6.1.5.5.7.3.36
+//id-kp-timeStamping
+1.3.6.1.5.5.7.3.8
+//id-kp-OCSPSigning
+1.3.6.1.5.5.7.3.9
+// MS C2PA Signing
+1.3.6.1.4.1.311.76.59.1.9
+";
+
+// extended key usages that can't be used for signing claims
+const TIME_STAMPING: &str = "1.3.6.1.5.5.7.3.8";
+const OCSP_SIGNING: &str = "1.3.6.1.5.5.7.3.9";
+
+// the extended key usage OIDs listed in the trust config, skipping // comments
+fn allowed_ekus(trust_config: Option<&str>) -> Vec<String> {
+    trust_config
+        .unwrap_or(DEFAULT_TRUST_CONFIG)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("//"))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Returns true if a key of this type can sign with `alg`.
+pub(crate) fn key_matches_alg(key: &PKeyRef<Public>, alg: SigningAlg) -> bool {
+    let curve = key.ec_key().ok().and_then(|k| k.group().curve_name());
+    match alg {
+        SigningAlg::Es256 => curve == Some(Nid::X9_62_PRIME256V1),
+        SigningAlg::Es384 => curve == Some(Nid::SECP384R1),
+        SigningAlg::Es512 => curve == Some(Nid::SECP521R1),
+        SigningAlg::Ps256 | SigningAlg::Ps384 | SigningAlg::Ps512 => {
+            key.id() == Id::RSA || key.id() == Id::RSA_PSS
+        }
+        SigningAlg::Ed25519 => key.id() == Id::ED25519,
+    }
+}
+
+// describes a public key for error messages, like "RSA" or "EC P-384"
+pub(crate) fn key_description(key: &PKeyRef<Public>) -> String {
+    match key.id() {
+        Id::RSA | Id::RSA_PSS => "RSA".to_owned(),
+        Id::ED25519 => "Ed25519".to_owned(),
+        Id::EC => {
+            let curve = key.ec_key().ok().and_then(|k| k.group().curve_name());
+            match curve {
+                Some(Nid::X9_62_PRIME256V1) => "EC P-256".to_owned(),
+                Some(Nid::SECP384R1) => "EC P-384".to_owned(),
+                Some(Nid::SECP521R1) => "EC P-521".to_owned(),
+                _ => "EC".to_owned(),
+            }
+        }
+        _ => "unsupported".to_owned(),
+    }
+}
+
+// names a certificate for error messages by its common name, or its whole subject
+fn cert_name(cert: &X509Ref) -> String {
+    let subject = cert.subject_name();
+    if let Some(cn) = subject
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|e| e.data().as_utf8().ok())
+    {
+        return format!("\"{}\"", cn);
+    }
+    let parts: Vec<String> = subject
+        .entries()
+        .filter_map(|e| e.data().as_utf8().ok().map(|d| d.to_string()))
+        .collect();
+    format!("\"{}\"", parts.join(", "))
+}
+
+/// Checks the signing certificate chain, and the private key if there is one.
+///
+/// Checks that the private key matches the signing certificate, that `alg` can be used
+/// with its key, that the certificates are valid now, that the signing certificate has
+/// an extended key usage allowed by `trust_config` (or c2pa's default trust config), and
+/// that each certificate in the chain is issued by the one after it. `name` describes the
+/// certificates in errors.
+pub(crate) fn check_sign_cert(
+    name: &str,
+    certs: &[u8],
+    private_key: Option<&[u8]>,
+    alg: SigningAlg,
+    trust_config: Option<&str>,
+) -> Result<()> {
+    let chain = X509::stack_from_pem(certs)
+        .ok()
+        .filter(|chain| !chain.is_empty())
+        .context(format!(
+            "The sign_cert {} contains no PEM certificates",
+            name
+        ))?;
+    let leaf = &chain[0];
+    let public_key = leaf.public_key()?;
+
+    if let Some(private_key) = private_key {
+        let private_key = PKey::private_key_from_pem(private_key)
+            .context("The private key is not a valid PEM private key")?;
+        if !private_key.public_eq(&public_key) {
+            bail!(
+                "The private key does not match the certificate {} in the sign_cert {}",
+                cert_name(leaf),
+                name
+            );
+        }
+    }
+
+    check_alg(name, leaf, &public_key, alg)?;
+
+    let now = Asn1Time::days_from_now(0)?;
+    for cert in &chain {
+        if cert.not_before() > now {
+            bail!(
+                "The certificate {} in the sign_cert {} is not valid until {}",
+                cert_name(cert),
+                name,
+                cert.not_before()
+            );
+        }
+        if cert.not_after() < now {
+            bail!(
+                "The certificate {} in the sign_cert {} expired on {}",
+                cert_name(cert),
+                name,
+                cert.not_after()
+            );
+        }
+    }
+
+    check_ekus(name, leaf, trust_config)?;
+
+    for (i, pair) in chain.windows(2).enumerate() {
+        let (cert, issuer) = (&pair[0], &pair[1]);
+        let issuer_key = issuer.public_key()?;
+        let signed_by_issuer = cert.verify(&issuer_key).unwrap_or(false);
+        if issuer.issued(cert) != X509VerifyResult::OK || !signed_by_issuer {
+            bail!(
+                "The certificates in the sign_cert {} are out of order: certificate {} ({}) was not issued by certificate {} ({}). The chain must start with the signing certificate, followed by the certificate that issued each one.",
+                name,
+                i + 1,
+                cert_name(cert),
+                i + 2,
+                cert_name(issuer)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn check_alg(name: &str, leaf: &X509Ref, key: &PKey<Public>, alg: SigningAlg) -> Result<()> {
+    if !key_matches_alg(key, alg) {
+        bail!(
+            "The certificate {} in the sign_cert {} has an {} key, which can't be used with alg {}",
+            cert_name(leaf),
+            name,
+            key_description(key),
+            alg
+        );
+    }
+    Ok(())
+}
+
+fn check_ekus(name: &str, leaf: &X509Ref, trust_config: Option<&str>) -> Result<()> {
+    let der = leaf.to_der()?;
+    let (_, cert) = X509Certificate::from_der(&der)
+        .map_err(|e| anyhow::anyhow!("The sign_cert {} can't be parsed: {}", name, e))?;
+    let allowed = allowed_ekus(trust_config);
+
+    let eku = match cert.extended_key_usage() {
+        Ok(Some(eku)) => eku.value,
+        _ => bail!(
+            "The certificate {} in the sign_cert {} has no extended key usage. It needs one of: {}",
+            cert_name(leaf),
+            name,
+            allowed.join(", ")
+        ),
+    };
+    if eku.any {
+        bail!(
+            "The certificate {} in the sign_cert {} has the anyExtendedKeyUsage extended key usage, which C2PA doesn't allow",
+            cert_name(leaf),
+            name
+        );
+    }
+
+    let mut ekus: Vec<String> = eku.other.iter().map(|oid| oid.to_id_string()).collect();
+    for (present, oid) in [
+        (eku.server_auth, "1.3.6.1.5.5.7.3.1"),
+        (eku.client_auth, "1.3.6.1.5.5.7.3.2"),
+        (eku.code_signing, "1.3.6.1.5.5.7.3.3"),
+        (eku.email_protection, "1.3.6.1.5.5.7.3.4"),
+        (eku.time_stamping, TIME_STAMPING),
+        (eku.ocsp_signing, OCSP_SIGNING),
+    ] {
+        if present {
+            ekus.push(oid.to_owned());
+        }
+    }
+
+    // time stamping and OCSP signing certificates can't also sign claims
+    let signing = ekus
+        .iter()
+        .any(|oid| allowed.contains(oid) && oid != TIME_STAMPING && oid != OCSP_SIGNING);
+    if !signing {
+        bail!(
+            "The certificate {} in the sign_cert {} has extended key usages {}, but needs one of: {}",
+            cert_name(leaf),
+            name,
+            ekus.join(", "),
+            allowed
+                .iter()
+                .filter(|oid| *oid != TIME_STAMPING && *oid != OCSP_SIGNING)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use openssl::{
+        asn1::Asn1Integer,
+        bn::BigNum,
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        pkey::Private,
+        x509::{
+            extension::{BasicConstraints, ExtendedKeyUsage, KeyUsage},
+            X509Name,
+        },
+    };
+
+    use super::*;
+
+    const CERTS: &[u8] = include_bytes!("../sample/es256_certs.pem");
+    const KEY: &[u8] = include_bytes!("../sample/es256_private.key");
+    const PS256_CERTS: &[u8] = include_bytes!("../sample/ps256.pub");
+    const PS256_KEY: &[u8] = include_bytes!("../sample/ps256.pem");
+
+    fn ec_key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    // builds a certificate for `key` named `cn`, issued by `issuer`, or self-signed,
+    // valid between the two numbers of days from now
+    fn make_cert(
+        cn: &str,
+        key: &PKey<Private>,
+        issuer: Option<(&X509, &PKey<Private>)>,
+        days: (i64, i64),
+        eku: Option<ExtendedKeyUsage>,
+    ) -> X509 {
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = Asn1Integer::from_bn(&BigNum::from_u32(1).unwrap()).unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder
+            .set_issuer_name(issuer.map(|(c, _)| c.subject_name()).unwrap_or(&name))
+            .unwrap();
+        builder.set_pubkey(key).unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        builder
+            .set_not_before(&Asn1Time::from_unix(now + days.0 * 86400).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::from_unix(now + days.1 * 86400).unwrap())
+            .unwrap();
+        match eku {
+            Some(eku) => {
+                builder.append_extension(eku.build().unwrap()).unwrap();
+                builder
+                    .append_extension(KeyUsage::new().digital_signature().build().unwrap())
+                    .unwrap();
+            }
+            None => {
+                builder
+                    .append_extension(BasicConstraints::new().ca().build().unwrap())
+                    .unwrap();
+            }
+        }
+        let signing_key = issuer.map(|(_, k)| k).unwrap_or(key);
+        builder.sign(signing_key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    fn email_eku() -> Option<ExtendedKeyUsage> {
+        let mut eku = ExtendedKeyUsage::new();
+        eku.email_protection();
+        Some(eku)
+    }
+
+    fn pem(certs: &[&X509]) -> Vec<u8> {
+        certs.iter().flat_map(|c| c.to_pem().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_sample_certs_pass() {
+        check_sign_cert("es256", CERTS, Some(KEY), SigningAlg::Es256, None).unwrap();
+        check_sign_cert(
+            "ps256",
+            PS256_CERTS,
+            Some(PS256_KEY),
+            SigningAlg::Ps384,
+            None,
+        )
+        .unwrap();
+        check_sign_cert("es256", CERTS, None, SigningAlg::Es256, None).unwrap();
+    }
+
+    #[test]
+    fn test_key_not_matching_cert() {
+        let error = check_sign_cert("es256", CERTS, Some(PS256_KEY), SigningAlg::Es256, None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("private key does not match the certificate \"C2PA Signer\""));
+    }
+
+    #[test]
+    fn test_alg_not_matching_key() {
+        let error = check_sign_cert("es256", CERTS, None, SigningAlg::Ps256, None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("has an EC P-256 key, which can't be used with alg ps256"));
+        let error = check_sign_cert("ps256", PS256_CERTS, None, SigningAlg::Ed25519, None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("has an RSA key, which can't be used with alg ed25519"));
+    }
+
+    #[test]
+    fn test_validity_dates() {
+        let ca_key = ec_key();
+        let ca = make_cert("Test CA", &ca_key, None, (0, 365), None);
+        let key = ec_key();
+
+        let expired = make_cert(
+            "Expired",
+            &key,
+            Some((&ca, &ca_key)),
+            (-20, -10),
+            email_eku(),
+        );
+        let error = check_sign_cert(
+            "test",
+            &pem(&[&expired, &ca]),
+            None,
+            SigningAlg::Es256,
+            None,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("certificate \"Expired\" in the sign_cert test expired on"));
+
+        let future = make_cert("Future", &key, Some((&ca, &ca_key)), (10, 20), email_eku());
+        let error = check_sign_cert("test", &pem(&[&future, &ca]), None, SigningAlg::Es256, None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("is not valid until"));
+    }
+
+    #[test]
+    fn test_extended_key_usages() {
+        let ca_key = ec_key();
+        let ca = make_cert("Test CA", &ca_key, None, (0, 365), None);
+        let key = ec_key();
+
+        let mut server = ExtendedKeyUsage::new();
+        server.server_auth();
+        let cert = make_cert("Server", &key, Some((&ca, &ca_key)), (0, 30), Some(server));
+        let error = check_sign_cert("test", &pem(&[&cert, &ca]), None, SigningAlg::Es256, None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("has extended key usages 1.3.6.1.5.5.7.3.1, but needs one of"));
+        // unless the trust config allows it
+        let trust_config = "//id-kp-serverAuth\n1.3.6.1.5.5.7.3.1\n";
+        check_sign_cert(
+            "test",
+            &pem(&[&cert, &ca]),
+            None,
+            SigningAlg::Es256,
+            Some(trust_config),
+        )
+        .unwrap();
+
+        let mut document = ExtendedKeyUsage::new();
+        document.other("1.3.6.1.5.5.7.3.36");
+        let cert = make_cert(
+            "Document",
+            &key,
+            Some((&ca, &ca_key)),
+            (0, 30),
+            Some(document),
+        );
+        check_sign_cert("test", &pem(&[&cert, &ca]), None, SigningAlg::Es256, None).unwrap();
+    }
+
+    #[test]
+    fn test_chain_order() {
+        let ca_key = ec_key();
+        let ca = make_cert("Test CA", &ca_key, None, (0, 365), None);
+        let key = ec_key();
+        let cert = make_cert("Signer", &key, Some((&ca, &ca_key)), (0, 30), email_eku());
+
+        check_sign_cert(
+            "test",
+            &pem(&[&cert, &ca]),
+            Some(&key.private_key_to_pem_pkcs8().unwrap()),
+            SigningAlg::Es256,
+            None,
+        )
+        .unwrap();
+
+        // the signing certificate must come first
+        let error = check_sign_cert("test", &pem(&[&ca, &cert]), None, SigningAlg::Es256, None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("has no extended key usage"));
+
+        let other_ca = make_cert("Other CA", &ec_key(), None, (0, 365), None);
+        let error = check_sign_cert(
+            "test",
+            &pem(&[&cert, &other_ca]),
+            None,
+            SigningAlg::Es256,
+            None,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("out of order: certificate 1 (\"Signer\") was not issued by certificate 2 (\"Other CA\")"));
+    }
+}
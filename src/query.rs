@@ -0,0 +1,337 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+//! Queries the manifest store of an asset with a path expression, like
+//! `active.assertions["c2pa.actions"].actions[*].action`, so that a single field can be
+//! checked without processing the whole report.
+//!
+//! Expressions are a subset of JSONPath: an optional `$` root, `.name` and `["name"]`
+//! children, `[0]` and `[-1]` indexes, `*` and `[*]` wildcards and `..` recursive descent.
+
+use anyhow::{bail, Context, Result};
+use c2pa::Reader;
+use serde_json::{Map, Value};
+
+/// One step of a path expression.
+#[derive(Debug, PartialEq)]
+enum Segment {
+    /// The member of an object with a name.
+    Child(String),
+    /// The item of an array at an index, counting from the end when negative.
+    Index(i64),
+    /// Every member of an object or item of an array.
+    Wildcard,
+    /// A step applied to a value and every value nested in it.
+    Descendants(Box<Segment>),
+}
+
+// parses a path expression into its segments
+fn parse(expression: &str) -> Result<Vec<Segment>> {
+    let chars: Vec<char> = expression.trim().chars().collect();
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    if chars.first() == Some(&'$') {
+        pos = 1;
+    } else if chars.first().is_some_and(|&c| is_name_char(c) || c == '*') {
+        // the first name doesn't need a dot
+        let segment = parse_name(&chars, &mut pos);
+        segments.push(segment);
+    }
+    while pos < chars.len() {
+        let descendants = chars[pos..].starts_with(&['.', '.']);
+        let segment = match chars[pos] {
+            '.' => {
+                pos += if descendants { 2 } else { 1 };
+                match chars.get(pos) {
+                    Some('[') => parse_bracket(&chars, &mut pos)?,
+                    Some(&c) if is_name_char(c) || c == '*' => parse_name(&chars, &mut pos),
+                    _ => bail!("Expected a name after '.' at position {}", pos),
+                }
+            }
+            '[' => parse_bracket(&chars, &mut pos)?,
+            c => bail!("Unexpected '{}' at position {}", c, pos),
+        };
+        segments.push(match descendants {
+            true => Segment::Descendants(Box::new(segment)),
+            false => segment,
+        });
+    }
+    Ok(segments)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-:@".contains(c)
+}
+
+// a name or `*` after a dot
+fn parse_name(chars: &[char], pos: &mut usize) -> Segment {
+    if chars[*pos] == '*' {
+        *pos += 1;
+        return Segment::Wildcard;
+    }
+    let start = *pos;
+    while *pos < chars.len() && is_name_char(chars[*pos]) {
+        *pos += 1;
+    }
+    Segment::Child(chars[start..*pos].iter().collect())
+}
+
+// a quoted name, an index or `*` in brackets
+fn parse_bracket(chars: &[char], pos: &mut usize) -> Result<Segment> {
+    let start = *pos;
+    *pos += 1;
+    let segment = match chars.get(*pos) {
+        Some('*') => {
+            *pos += 1;
+            Segment::Wildcard
+        }
+        Some(&quote) if quote == '"' || quote == '\'' => {
+            *pos += 1;
+            let mut name = String::new();
+            loop {
+                match chars.get(*pos) {
+                    Some('\\') => {
+                        name.extend(chars.get(*pos + 1));
+                        *pos += 2;
+                    }
+                    Some(&c) if c == quote => {
+                        *pos += 1;
+                        break;
+                    }
+                    Some(&c) => {
+                        name.push(c);
+                        *pos += 1;
+                    }
+                    None => bail!("Unterminated string at position {}", start + 1),
+                }
+            }
+            Segment::Child(name)
+        }
+        _ => {
+            let digits = *pos;
+            while *pos < chars.len() && (chars[*pos].is_ascii_digit() || chars[*pos] == '-') {
+                *pos += 1;
+            }
+            let index: String = chars[digits..*pos].iter().collect();
+            Segment::Index(index.parse().context(format!(
+                "Expected an index, name or * at position {}",
+                digits
+            ))?)
+        }
+    };
+    if chars.get(*pos) != Some(&']') {
+        bail!("Expected ']' at position {}", *pos);
+    }
+    *pos += 1;
+    Ok(segment)
+}
+
+// the values a segment selects from a value
+fn select<'a>(value: &'a Value, segment: &Segment, out: &mut Vec<&'a Value>) {
+    match (segment, value) {
+        (Segment::Child(name), Value::Object(map)) => out.extend(map.get(name)),
+        (Segment::Index(index), Value::Array(items)) => {
+            let index = match *index < 0 {
+                true => items.len() as i64 + index,
+                false => *index,
+            };
+            if index >= 0 {
+                out.extend(items.get(index as usize));
+            }
+        }
+        (Segment::Wildcard, Value::Object(map)) => out.extend(map.values()),
+        (Segment::Wildcard, Value::Array(items)) => out.extend(items),
+        (Segment::Descendants(inner), _) => {
+            select(value, inner, out);
+            match value {
+                Value::Object(map) => map.values().for_each(|v| select(v, segment, out)),
+                Value::Array(items) => items.iter().for_each(|v| select(v, segment, out)),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+// a manifest with its assertions keyed by label and the manifests of its ingredients nested
+// in them, so both can be addressed by path
+fn manifest_model(manifests: &Map<String, Value>, label: &str, parents: &mut Vec<String>) -> Value {
+    let Some(Value::Object(manifest)) = manifests.get(label) else {
+        return Value::Null;
+    };
+    let mut manifest = manifest.clone();
+    parents.push(label.to_owned());
+
+    if let Some(Value::Array(assertions)) = manifest.get("assertions") {
+        let mut by_label = Map::new();
+        for assertion in assertions {
+            let mut label = assertion["label"].as_str().unwrap_or_default().to_owned();
+            // keyed like their labels in the store, where the second instance is `__1`
+            if let Some(instance) = assertion["instance"].as_u64().filter(|&i| i > 0) {
+                label = format!("{}__{}", label, instance);
+            }
+            by_label.insert(label, assertion["data"].clone());
+        }
+        manifest.insert("assertions".to_owned(), Value::Object(by_label));
+    }
+
+    if let Some(Value::Array(ingredients)) = manifest.get_mut("ingredients") {
+        for ingredient in ingredients.iter_mut().filter_map(Value::as_object_mut) {
+            let Some(label) = ingredient.get("active_manifest").and_then(Value::as_str) else {
+                continue;
+            };
+            // a manifest store with a loop would otherwise nest forever
+            if !parents.iter().any(|p| p == label) {
+                let label = label.to_owned();
+                let model = manifest_model(manifests, &label, parents);
+                ingredient.insert("manifest".to_owned(), model);
+            }
+        }
+    }
+
+    parents.pop();
+    Value::Object(manifest)
+}
+
+// the model expressions are evaluated against: the active manifest and the validation status
+fn model(reader: &Reader) -> Result<Value> {
+    let report = crate::format::reader_report(reader, false)?;
+    let empty = Map::new();
+    let manifests = report["manifests"].as_object().unwrap_or(&empty);
+    let mut model = Map::new();
+    model.insert(
+        "active_manifest".to_owned(),
+        report["active_manifest"].clone(),
+    );
+    let active = match report["active_manifest"].as_str() {
+        Some(label) => manifest_model(manifests, label, &mut Vec::new()),
+        None => Value::Null,
+    };
+    model.insert("active".to_owned(), active);
+    if let Some(status) = report.get("validation_status") {
+        model.insert("validation_status".to_owned(), status.clone());
+    }
+    Ok(Value::Object(model))
+}
+
+// the values that segments select from a model
+fn evaluate(model: &Value, segments: &[Segment]) -> Vec<Value> {
+    let mut values = vec![model];
+    for segment in segments {
+        let mut selected = Vec::new();
+        for value in values {
+            select(value, segment, &mut selected);
+        }
+        values = selected;
+    }
+    values.into_iter().cloned().collect()
+}
+
+/// The values that a path expression selects from the manifest store of `reader`.
+///
+/// The root has the `active_manifest` label, the `active` manifest and the `validation_status`.
+/// Manifests have their assertions keyed by label, like `assertions["c2pa.actions"]`, and
+/// ingredients with content credentials have their own manifest as `manifest`.
+pub fn query(reader: &Reader, expression: &str) -> Result<Vec<Value>> {
+    let segments = parse(expression).context(format!("Invalid query {:?}", expression))?;
+    Ok(evaluate(&model(reader)?, &segments))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(r#"active.assertions["c2pa.actions"].actions[*].action"#).unwrap(),
+            vec![
+                Segment::Child("active".to_owned()),
+                Segment::Child("assertions".to_owned()),
+                Segment::Child("c2pa.actions".to_owned()),
+                Segment::Child("actions".to_owned()),
+                Segment::Wildcard,
+                Segment::Child("action".to_owned()),
+            ]
+        );
+        assert_eq!(
+            parse("$..ingredients[-1]['title']").unwrap(),
+            vec![
+                Segment::Descendants(Box::new(Segment::Child("ingredients".to_owned()))),
+                Segment::Index(-1),
+                Segment::Child("title".to_owned()),
+            ]
+        );
+        assert_eq!(parse("$").unwrap(), vec![]);
+        assert!(parse("active[").is_err());
+        assert!(parse("active['title").is_err());
+        assert!(parse("active.").is_err());
+        assert!(parse("active[x]").is_err());
+    }
+
+    #[test]
+    fn test_model() {
+        let report = json!({
+            "active_manifest": "b",
+            "manifests": {
+                "a": {"label": "a", "assertions": [{"label": "c2pa.actions", "data": {"actions": [{"action": "c2pa.created"}]}}]},
+                "b": {
+                    "label": "b",
+                    "ingredients": [{"title": "A", "active_manifest": "a"}, {"title": "plain"}],
+                    "assertions": [
+                        {"label": "c2pa.actions", "data": {"actions": [{"action": "c2pa.opened"}, {"action": "c2pa.edited"}]}},
+                        {"label": "c2pa.ingredient", "data": {"n": 1}},
+                        {"label": "c2pa.ingredient", "data": {"n": 2}, "instance": 1}
+                    ]
+                }
+            }
+        });
+        let manifests = report["manifests"].as_object().unwrap();
+        let model = json!({"active": manifest_model(manifests, "b", &mut Vec::new())});
+        let query = |expression: &str| evaluate(&model, &parse(expression).unwrap());
+
+        assert_eq!(
+            query(r#"active.assertions["c2pa.actions"].actions[*].action"#),
+            vec![json!("c2pa.opened"), json!("c2pa.edited")]
+        );
+        assert_eq!(
+            query(r#"active.assertions["c2pa.ingredient__1"].n"#),
+            vec![json!(2)]
+        );
+        assert_eq!(
+            query(r#"active.assertions["c2pa.ingredient"].n"#),
+            vec![json!(1)]
+        );
+        assert_eq!(
+            query("active.ingredients[0].manifest.label"),
+            vec![json!("a")]
+        );
+        assert_eq!(
+            query("$..action"),
+            vec![
+                json!("c2pa.created"),
+                json!("c2pa.opened"),
+                json!("c2pa.edited")
+            ]
+        );
+        assert_eq!(
+            query("active.ingredients[*].title"),
+            vec![json!("A"), json!("plain")]
+        );
+        assert!(query("active.ingredients[2]").is_empty());
+        assert!(query("active.missing").is_empty());
+    }
+}
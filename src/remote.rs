@@ -0,0 +1,452 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+use std::{
+    fs::File,
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use url::Url;
+
+const DC_TERMS: &str = "http://purl.org/dc/terms/";
+
+// how long to wait for a remote manifest store to download
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A local folder or HTTP server that holds copies of remote manifest stores.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StoreFolder {
+    Local(PathBuf),
+    Server(Url),
+}
+
+/// Where a single manifest store is read from or written to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StoreLocation {
+    File(PathBuf),
+    Url(Url),
+}
+
+impl FromStr for StoreFolder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match Url::parse(s) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Self::Server(url),
+            _ => Self::Local(PathBuf::from(s)),
+        })
+    }
+}
+
+impl StoreFolder {
+    /// The location of a manifest store at a URL path relative to this folder.
+    ///
+    /// The path can't leave the folder, so `.` and `..` segments are rejected.
+    fn join(&self, path: &str) -> Result<StoreLocation> {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        if segments.is_empty() {
+            bail!("no path to a manifest store in {:?}", path);
+        }
+        if let Some(segment) = segments
+            .iter()
+            .find(|s| matches!(**s, "." | "..") || s.contains('\\'))
+        {
+            bail!(
+                "invalid segment {:?} in manifest store path {:?}",
+                segment,
+                path
+            );
+        }
+        Ok(match self {
+            Self::Local(dir) => {
+                StoreLocation::File(segments.iter().fold(dir.clone(), |p, s| p.join(s)))
+            }
+            Self::Server(base) => {
+                let mut url = base.clone();
+                url.path_segments_mut()
+                    .map_err(|_| anyhow::anyhow!("{} can't hold manifest stores", base))?
+                    .pop_if_empty()
+                    .extend(&segments);
+                StoreLocation::Url(url)
+            }
+        })
+    }
+}
+
+/// Maps a remote manifest URL prefix to a local folder or HTTP server.
+///
+/// Given as `<URL_PREFIX>=<FOLDER_OR_URL>`. The rest of a matching URL after the prefix
+/// is appended to the target.
+#[derive(Clone, Debug)]
+pub struct RemoteMapping {
+    prefix: String,
+    target: StoreFolder,
+}
+
+impl FromStr for RemoteMapping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (prefix, target) = s
+            .split_once('=')
+            .context("expected <URL_PREFIX>=<FOLDER_OR_URL>")?;
+        if prefix.is_empty() || target.is_empty() {
+            bail!("expected <URL_PREFIX>=<FOLDER_OR_URL>");
+        }
+        Ok(Self {
+            prefix: prefix.to_owned(),
+            target: target.parse()?,
+        })
+    }
+}
+
+/// Resolves remote manifest URLs to local copies so assets can be verified offline.
+#[derive(Debug, Default)]
+pub struct RemoteResolver {
+    mappings: Vec<RemoteMapping>,
+}
+
+impl RemoteResolver {
+    pub fn new(mappings: Vec<RemoteMapping>) -> Self {
+        Self { mappings }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// Returns where the manifest store for `url` is read from, if a mapping matches.
+    ///
+    /// The longest matching prefix wins.
+    pub fn resolve(&self, url: &str) -> Result<Option<StoreLocation>> {
+        // parsing removes `.` and `..` segments, so the rest of the URL can't climb above
+        // the prefix it matches
+        let url =
+            Url::parse(url).with_context(|| format!("remote manifest URL {} is not valid", url))?;
+        let url = url.as_str();
+        let Some(mapping) = self
+            .mappings
+            .iter()
+            .filter(|m| url.starts_with(&m.prefix))
+            .max_by_key(|m| m.prefix.len())
+        else {
+            return Ok(None);
+        };
+        let rest = url[mapping.prefix.len()..]
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default();
+        mapping.target.join(rest).map(Some)
+    }
+
+    /// Reads the manifest store for `url` from its local mapping, or from `url` itself when
+    /// there are no mappings.
+    ///
+    /// Returns the manifest store and a description of where it was read from.
+    pub fn fetch(&self, url: &str) -> Result<(Vec<u8>, String)> {
+        match self.resolve(url)? {
+            Some(StoreLocation::File(path)) => {
+                let data = std::fs::read(&path)
+                    .with_context(|| format!("Failed to read remote manifest from {:?}", path))?;
+                Ok((data, path.display().to_string()))
+            }
+            Some(StoreLocation::Url(local)) => Ok((get(local.as_str())?, local.to_string())),
+            None if self.is_empty() => Ok((get(url)?, url.to_owned())),
+            None => bail!("No local mapping for remote manifest {}", url),
+        }
+    }
+}
+
+// downloads a manifest store
+fn get(url: &str) -> Result<Vec<u8>> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()?;
+    let data = client
+        .get(url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.bytes())
+        .with_context(|| format!("Failed to read remote manifest from {}", url))?;
+    Ok(data.to_vec())
+}
+
+/// Publishes a manifest store so it can be fetched from `remote_url`.
+///
+/// The store is written below a local folder or uploaded with an HTTP PUT to a server,
+/// at the path of `remote_url`. An existing store is only replaced when `force` is set.
+/// A store written to a folder is read back, and an uploaded store is fetched back from
+/// `remote_url`, and compared with what was published. Returns where the store was published.
+pub fn publish(
+    manifest_data: &[u8],
+    remote_url: &str,
+    target: &StoreFolder,
+    force: bool,
+) -> Result<String> {
+    let url = Url::parse(remote_url).context("remote manifest URL is not valid")?;
+    let location = target.join(url.path()).with_context(|| {
+        format!(
+            "remote manifest URL {} has no path to publish to",
+            remote_url
+        )
+    })?;
+
+    let (published, fetched_from, location) = match location {
+        StoreLocation::File(path) => {
+            if path.exists() && !force {
+                bail!(
+                    "Manifest store {:?} already exists, use -f/force to force write",
+                    path
+                );
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, manifest_data)
+                .with_context(|| format!("Failed to write manifest store to {:?}", path))?;
+            let location = path.display().to_string();
+            (std::fs::read(&path)?, location.clone(), location)
+        }
+        StoreLocation::Url(upload) => {
+            let client = reqwest::blocking::Client::new();
+            if !force {
+                let existing = client
+                    .head(upload.clone())
+                    .send()
+                    .with_context(|| format!("Failed to check for manifest store at {}", upload))?;
+                if existing.status().is_success() {
+                    bail!(
+                        "Manifest store {} already exists, use -f/force to force write",
+                        upload
+                    );
+                }
+            }
+            client
+                .put(upload.clone())
+                .header("Content-Type", "application/c2pa")
+                .body(manifest_data.to_vec())
+                .send()
+                .and_then(|r| r.error_for_status())
+                .with_context(|| format!("Failed to upload manifest store to {}", upload))?;
+            // check what a validator will get, which may be served from elsewhere
+            let fetched = client
+                .get(url)
+                .send()
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.bytes())
+                .with_context(|| {
+                    format!("Failed to fetch published manifest from {}", remote_url)
+                })?;
+            (fetched.to_vec(), remote_url.to_owned(), upload.to_string())
+        }
+    };
+
+    if published != manifest_data {
+        bail!(
+            "manifest store fetched back from {} does not match the one published",
+            fetched_from
+        );
+    }
+    Ok(location)
+}
+
+/// Returns the remote manifest URL of an asset that has no embedded manifest store.
+///
+/// The URL is read from the asset's XMP `dcterms:provenance` value.
+pub fn remote_url(path: &Path) -> Result<Option<String>> {
+    let format = c2pa::format_from_path(path).context("unsupported file type")?;
+    let mut file = File::open(path)?;
+    match c2pa::jumbf_io::load_jumbf_from_stream(&format, &mut file) {
+        Err(c2pa::Error::JumbfNotFound) => {}
+        _ => return Ok(None),
+    }
+    file.rewind()?;
+    provenance_url(&mut file)
+}
+
+/// Returns the remote manifest URL in the XMP `dcterms:provenance` value of an asset, without
+/// checking for an embedded manifest store.
+///
+/// The asset is searched for XMP packets in chunks, so it is never read into memory whole.
+pub fn provenance_url(stream: &mut impl Read) -> Result<Option<String>> {
+    Ok(find_provenance(stream, CHUNK_SIZE)?.filter(|p| p.starts_with("http")))
+}
+
+// how much of an asset is read at a time when searching it for XMP
+const CHUNK_SIZE: usize = 64 * 1024;
+// the largest XMP packet that is read, so an unterminated packet can't fill memory
+const MAX_PACKET_SIZE: usize = 4 * 1024 * 1024;
+
+// finds the XMP packets in the raw bytes of an asset and reads dcterms:provenance from them
+fn find_provenance(stream: &mut impl Read, chunk_size: usize) -> Result<Option<String>> {
+    const START: &[u8] = b"<x:xmpmeta";
+    const END: &[u8] = b"</x:xmpmeta>";
+
+    let mut chunk = vec![0u8; chunk_size];
+    // unsearched bytes, or the bytes of a packet whose end hasn't been read yet
+    let mut pending = Vec::new();
+    // how much of a pending packet has been searched for its end
+    let mut searched = 0;
+    loop {
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        pending.extend_from_slice(&chunk[..read]);
+
+        loop {
+            if searched == 0 {
+                match find(&pending, START) {
+                    Some(start) => {
+                        pending.drain(..start);
+                        searched = START.len();
+                    }
+                    None => {
+                        // keep what could be the beginning of a start tag
+                        let keep = pending.len().min(START.len() - 1);
+                        pending.drain(..pending.len() - keep);
+                        break;
+                    }
+                }
+            }
+            let from = searched.saturating_sub(END.len() - 1);
+            match find(&pending[from..], END) {
+                Some(end) => {
+                    let end = from + end + END.len();
+                    if let Some(provenance) = std::str::from_utf8(&pending[..end])
+                        .ok()
+                        .and_then(provenance_from_xmp)
+                    {
+                        return Ok(Some(provenance));
+                    }
+                    pending.drain(..end);
+                    searched = 0;
+                }
+                None if pending.len() > MAX_PACKET_SIZE => {
+                    // give up on this packet and look for the next one
+                    pending.drain(..START.len());
+                    searched = 0;
+                }
+                None => {
+                    searched = pending.len();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn provenance_from_xmp(xmp: &str) -> Option<String> {
+    let doc = roxmltree::Document::parse(xmp).ok()?;
+    doc.descendants().find_map(|node| {
+        if node.tag_name().name() == "provenance" && node.tag_name().namespace() == Some(DC_TERMS) {
+            return node.text().map(|t| t.trim().to_owned());
+        }
+        node.attributes()
+            .find(|a| a.name() == "provenance" && a.namespace() == Some(DC_TERMS))
+            .map(|a| a.value().to_owned())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_longest_prefix() {
+        let resolver = RemoteResolver::new(vec![
+            "https://cdn.example.com/=/manifests".parse().unwrap(),
+            "https://cdn.example.com/video/=http://localhost:8080/video"
+                .parse()
+                .unwrap(),
+        ]);
+        assert_eq!(
+            resolver
+                .resolve("https://cdn.example.com/images/a.c2pa?v=1")
+                .unwrap(),
+            Some(StoreLocation::File(PathBuf::from(
+                "/manifests/images/a.c2pa"
+            )))
+        );
+        assert_eq!(
+            resolver
+                .resolve("https://cdn.example.com/video/b.c2pa")
+                .unwrap(),
+            Some(StoreLocation::Url(
+                Url::parse("http://localhost:8080/video/b.c2pa").unwrap()
+            ))
+        );
+        assert!(resolver
+            .resolve("https://other.example.com/a.c2pa")
+            .unwrap()
+            .is_none());
+        assert!("no-target".parse::<RemoteMapping>().is_err());
+    }
+
+    #[test]
+    fn test_resolve_stays_in_folder() {
+        let resolver = RemoteResolver::new(vec!["https://cdn.example.com/manifests/=/manifests"
+            .parse()
+            .unwrap()]);
+        // `..` segments are removed before matching, so they can't climb out of the prefix
+        assert!(resolver
+            .resolve("https://cdn.example.com/manifests/../../etc/passwd")
+            .unwrap()
+            .is_none());
+        assert!(resolver
+            .resolve("https://cdn.example.com/manifests/%2e%2e/%2E%2E/etc/passwd")
+            .unwrap()
+            .is_none());
+        assert!(resolver
+            .resolve("https://cdn.example.com/manifests/")
+            .is_err());
+
+        let folder = StoreFolder::Local(PathBuf::from("/manifests"));
+        assert!(folder.join("a/../../etc/passwd").is_err());
+        assert!(folder.join("./a.c2pa").is_err());
+        assert_eq!(
+            folder.join("/a/b.c2pa").unwrap(),
+            StoreLocation::File(PathBuf::from("/manifests/a/b.c2pa"))
+        );
+    }
+
+    #[test]
+    fn test_find_provenance() {
+        let xmp = br#"junk<x:xmpmeta xmlns:x="adobe:ns:meta/"></x:xmpmeta>junk<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description xmlns:dcterms="http://purl.org/dc/terms/" dcterms:provenance="https://cdn.example.com/a.c2pa"/></rdf:RDF></x:xmpmeta>junk"#;
+        // tags split across chunks are still found
+        for chunk_size in [1, 7, 64, CHUNK_SIZE] {
+            assert_eq!(
+                find_provenance(&mut &xmp[..], chunk_size)
+                    .unwrap()
+                    .as_deref(),
+                Some("https://cdn.example.com/a.c2pa")
+            );
+        }
+        assert_eq!(find_provenance(&mut &b"no xmp here"[..], 4).unwrap(), None);
+        assert_eq!(
+            find_provenance(&mut &b"junk<x:xmpmeta unterminated"[..], 4).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+use std::{
+    fmt,
+    fs::File,
+    io::Seek,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use c2pa::{Error, Reader};
+
+use crate::{
+    remote::{self, RemoteResolver},
+    special_errs,
+};
+
+/// Where the manifest store that was validated against an asset came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Binding {
+    /// Embedded in the asset.
+    Embedded,
+    /// Read from a separate manifest store file.
+    Sidecar(PathBuf),
+    /// Referenced by a remote URL in the asset, and read from that URL or a local mapping of it.
+    Remote { url: String, source: String },
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Embedded => write!(f, "Manifest binding: embedded"),
+            Binding::Sidecar(path) => write!(
+                f,
+                "Manifest binding: external, manifest store read from {:?}",
+                path
+            ),
+            Binding::Remote { url, source } => write!(
+                f,
+                "Manifest binding: remote {}, manifest store read from {}",
+                url, source
+            ),
+        }
+    }
+}
+
+/// Returns the sidecar manifest store path for an asset, `asset.c2pa` next to the asset.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    path.with_extension("c2pa")
+}
+
+/// Reads and validates the manifest store for an asset.
+///
+/// The manifest store is found with [`locate`] and validated against the asset's bytes.
+pub fn load(
+    path: &Path,
+    manifest_file: Option<&Path>,
+    remote: &RemoteResolver,
+) -> Result<(Reader, Binding)> {
+    let (manifest_data, binding) = locate(path, manifest_file, remote)?;
+    let reader = validate(path, &manifest_data)?;
+    Ok((reader, binding))
+}
+
+/// Finds the manifest store for an asset without validating it.
+///
+/// If `manifest_file` is given, that manifest store is used. Otherwise the manifest
+/// embedded in the asset is used, then a remote manifest referenced by the asset, and
+/// finally a sidecar manifest store next to the asset. Remote manifests are read through
+/// `remote`, which only fetches them from their original URL when it has no mappings.
+pub fn locate(
+    path: &Path,
+    manifest_file: Option<&Path>,
+    remote: &RemoteResolver,
+) -> Result<(Vec<u8>, Binding)> {
+    if let Some(manifest_file) = manifest_file {
+        let manifest_data = read_sidecar(manifest_file)?;
+        return Ok((manifest_data, Binding::Sidecar(manifest_file.to_path_buf())));
+    }
+
+    let format = c2pa::format_from_path(path)
+        .ok_or(Error::UnsupportedType)
+        .map_err(special_errs)?;
+    let mut file = File::open(path)?;
+    match c2pa::jumbf_io::load_jumbf_from_stream(&format, &mut file) {
+        Err(Error::JumbfNotFound) => {}
+        result => return Ok((result.map_err(special_errs)?, Binding::Embedded)),
+    }
+
+    file.rewind()?;
+    if let Some(url) = remote::provenance_url(&mut file)? {
+        let (manifest_data, source) = remote.fetch(&url)?;
+        return Ok((manifest_data, Binding::Remote { url, source }));
+    }
+
+    let sidecar = sidecar_path(path);
+    if sidecar != path && sidecar.exists() {
+        let manifest_data = read_sidecar(&sidecar)?;
+        return Ok((manifest_data, Binding::Sidecar(sidecar)));
+    }
+    Err(special_errs(Error::JumbfNotFound))
+}
+
+fn read_sidecar(manifest_file: &Path) -> Result<Vec<u8>> {
+    std::fs::read(manifest_file).context(format!("reading manifest file {:?}", manifest_file))
+}
+
+/// Validates a manifest store that is not embedded in the asset against the asset's bytes.
+pub fn validate(path: &Path, manifest_data: &[u8]) -> Result<Reader> {
+    let format = c2pa::format_from_path(path)
+        .ok_or(Error::UnsupportedType)
+        .map_err(special_errs)?;
+    Reader::from_manifest_data_and_stream(manifest_data, &format, File::open(path)?)
+        .map_err(special_errs)
+}
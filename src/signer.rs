@@ -12,14 +12,22 @@
 // each license.
 
 use std::{
+    collections::BTreeMap,
     env,
     path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use c2pa::{create_signer, Signer, SigningAlg};
 use serde::Deserialize;
 
+use crate::{
+    kms::SignerDef,
+    preflight::check_sign_cert,
+    private_key::{load_key, KeyPassphraseArgs},
+    tsa::TsaPolicy,
+};
+
 // Pull in default certs so the binary can self config
 const DEFAULT_CERTS: &[u8] = include_bytes!("../sample/es256_certs.pem");
 const DEFAULT_KEY: &[u8] = include_bytes!("../sample/es256_private.key");
@@ -27,7 +35,7 @@ const DEFAULT_KEY: &[u8] = include_bytes!("../sample/es256_private.key");
 pub fn get_ta_url() -> Option<String> {
     std::env::var("C2PA_TA_URL").ok()
 }
-#[derive(Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 pub struct SignConfig {
     /// Signing algorithm to use - must match the associated certs
     ///
@@ -35,11 +43,30 @@ pub struct SignConfig {
     /// Defaults to es256
     pub alg: Option<String>,
     /// A path to a file containing the private key required for signing
+    ///
+    /// Can be an encrypted PEM key, or a PKCS#12 file that also holds the certificate chain
     pub private_key: Option<PathBuf>,
     /// A path to a file containing the signing cert required for signing
     pub sign_cert: Option<PathBuf>,
     /// A Url to a Time Authority to use when signing the manifest
     pub ta_url: Option<String>,
+    /// More Time Authority Urls, tried in order if ta_url doesn't respond
+    pub ta_urls: Option<Vec<String>>,
+    /// What to do when no Time Authority responds: fail (the default) or skip the time stamp
+    pub ta_policy: Option<TsaPolicy>,
+    /// Seconds to wait for each Time Authority to respond
+    pub ta_timeout: Option<u64>,
+    /// A signer backend that holds the private key, used instead of private_key
+    pub signer: Option<SignerDef>,
+}
+
+/// A signer configuration file: default signing settings and named profiles.
+#[derive(Debug, Default, Deserialize)]
+struct SignerConfigFile {
+    #[serde(flatten)]
+    defaults: SignConfig,
+    #[serde(default)]
+    profiles: BTreeMap<String, SignConfig>,
 }
 
 impl SignConfig {
@@ -47,6 +74,88 @@ impl SignConfig {
         serde_json::from_str(json).context("reading manifest configuration")
     }
 
+    /// Reads signing settings from a signer configuration file, applying the named profile.
+    ///
+    /// The file holds the same fields as the manifest definition's signing settings, and
+    /// optionally a `profiles` object of named settings. Fields set in the profile override
+    /// the ones at the top level of the file. Paths are relative to the file.
+    pub fn from_file(path: &Path, profile: Option<&str>) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .context(format!("reading signer configuration {:?}", path))?;
+        let mut file: SignerConfigFile = serde_json::from_str(&json)
+            .context(format!("reading signer configuration {:?}", path))?;
+
+        let mut config = match profile {
+            Some(name) => match file.profiles.remove(name) {
+                Some(profile) => profile.or(file.defaults),
+                None => bail!(
+                    "No signer profile {:?} in {:?}. Available profiles: {}",
+                    name,
+                    path,
+                    file.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                ),
+            },
+            None => file.defaults,
+        };
+        if let Some(base) = std::fs::canonicalize(path)?.parent() {
+            config.set_base_path(base);
+        }
+        Ok(config)
+    }
+
+    /// Returns these settings, with any field that isn't set taken from `other`.
+    ///
+    /// The credential, `alg`, `private_key`, `sign_cert` and `signer`, is taken as a whole from
+    /// these settings if they set any part of it, so a key is never used with a certificate or
+    /// algorithm from another source.
+    pub fn or(self, other: SignConfig) -> SignConfig {
+        let (alg, private_key, sign_cert, signer) = match self.has_credential() {
+            true => (self.alg, self.private_key, self.sign_cert, self.signer),
+            false => (other.alg, other.private_key, other.sign_cert, other.signer),
+        };
+        SignConfig {
+            alg,
+            private_key,
+            sign_cert,
+            ta_url: self.ta_url.or(other.ta_url),
+            ta_urls: self.ta_urls.or(other.ta_urls),
+            ta_policy: self.ta_policy.or(other.ta_policy),
+            ta_timeout: self.ta_timeout.or(other.ta_timeout),
+            signer,
+        }
+    }
+
+    // whether any part of the credential is set
+    fn has_credential(&self) -> bool {
+        self.alg.is_some()
+            || self.private_key.is_some()
+            || self.sign_cert.is_some()
+            || self.signer.is_some()
+    }
+
+    /// Returns the Time Authority Urls to try in order: ta_url followed by ta_urls, or else
+    /// the comma separated Urls of the C2PA_TA_URL environment variable.
+    pub fn tsa_urls(&self) -> Vec<String> {
+        let urls: Vec<String> = self
+            .ta_url
+            .iter()
+            .chain(self.ta_urls.iter().flatten())
+            .cloned()
+            .collect();
+        if !urls.is_empty() {
+            return urls;
+        }
+        get_ta_url()
+            .map(|urls| {
+                urls.split(',')
+                    .map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     // set a base for all non-absolute paths
     pub fn set_base_path<P: AsRef<Path>>(&mut self, base: P) -> &Self {
         if let Some(path) = self.private_key.as_ref() {
@@ -62,10 +171,17 @@ impl SignConfig {
         self
     }
 
-    pub fn signer(&self) -> Result<Box<dyn Signer>> {
+    /// Creates a signer from the private key and certificates, decrypting the private key
+    /// with a passphrase from `passphrase` if it is encrypted. The signing certificate is
+    /// checked against the extended key usages of `trust_config`.
+    pub fn signer(
+        &self,
+        passphrase: &KeyPassphraseArgs,
+        trust_config: Option<&str>,
+    ) -> Result<Box<dyn Signer>> {
         let alg = self.alg.as_deref().unwrap_or("es256").to_lowercase();
         let alg: SigningAlg = alg.parse().map_err(|_| c2pa::Error::UnsupportedType)?;
-        let tsa_url = self.ta_url.clone().or_else(get_ta_url);
+        let tsa_url = self.tsa_urls().into_iter().next();
 
         let mut private_key = None;
         let mut sign_cert = None;
@@ -81,6 +197,17 @@ impl SignConfig {
             }
         };
 
+        // decrypt the key, and take the certificate chain from a PKCS#12 file
+        if let Some(key) = private_key.take() {
+            let name = match self.private_key.as_deref() {
+                Some(path) => format!("{:?}", path),
+                None => "C2PA_PRIVATE_KEY".to_owned(),
+            };
+            let loaded = load_key(&key, &name, passphrase)?;
+            private_key = Some(loaded.private_key);
+            sign_cert = loaded.certs;
+        }
+
         if let Some(path) = self.sign_cert.as_deref() {
             sign_cert =
                 Some(std::fs::read(path).context(format!("Reading sign cert: {:?}", &path))?);
@@ -94,6 +221,11 @@ impl SignConfig {
 
         if let Some(private_key) = private_key {
             if let Some(sign_cert) = sign_cert {
+                let name = match self.sign_cert.as_deref() {
+                    Some(path) => format!("{:?}", path),
+                    None => "C2PA_SIGN_CERT".to_owned(),
+                };
+                check_sign_cert(&name, &sign_cert, Some(&private_key), alg, trust_config)?;
                 let signer = create_signer::from_keys(&sign_cert, &private_key, alg, tsa_url)
                     .context("Invalid certification data")?;
                 return Ok(signer);
@@ -129,15 +261,100 @@ pub mod tests {
         let mut sign_config = SignConfig::from_json(CONFIG).expect("from_json");
         sign_config.set_base_path("sample");
 
-        let signer = sign_config.signer().expect("get signer");
+        let signer = sign_config
+            .signer(&KeyPassphraseArgs::default(), None)
+            .expect("get signer");
         assert_eq!(signer.alg(), SigningAlg::Es256);
     }
 
+    #[test]
+    fn test_signer_config_profiles() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("signer.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "alg": "es256",
+                "sign_cert": "certs.pem",
+                "ta_url": "http://timestamp.example.com",
+                "profiles": {
+                    "prod": { "alg": "ps256", "private_key": "/keys/prod.pem" },
+                    "staging": { "private_key": "/keys/staging.pem", "sign_cert": "staging.pem" },
+                    "dev": { "ta_url": "http://localhost:3161" }
+                }
+            }"#,
+        )
+        .unwrap();
+        let base = std::fs::canonicalize(tempdir.path()).unwrap();
+
+        // a profile's credential doesn't take parts from the top level
+        let config = SignConfig::from_file(&path, Some("prod")).unwrap();
+        assert_eq!(config.alg.as_deref(), Some("ps256"));
+        assert_eq!(config.private_key, Some(PathBuf::from("/keys/prod.pem")));
+        assert_eq!(config.sign_cert, None);
+        assert_eq!(
+            config.ta_url.as_deref(),
+            Some("http://timestamp.example.com")
+        );
+
+        let config = SignConfig::from_file(&path, Some("staging")).unwrap();
+        assert_eq!(config.alg, None);
+        assert_eq!(config.sign_cert, Some(base.join("staging.pem")));
+
+        let config = SignConfig::from_file(&path, Some("dev")).unwrap();
+        assert_eq!(config.ta_url.as_deref(), Some("http://localhost:3161"));
+        assert_eq!(config.alg.as_deref(), Some("es256"));
+        assert_eq!(config.sign_cert, Some(base.join("certs.pem")));
+
+        let error = SignConfig::from_file(&path, Some("test")).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Available profiles: dev, prod, staging"));
+    }
+
+    #[test]
+    fn test_signer_config_overrides_manifest() {
+        let manifest = SignConfig {
+            alg: Some("ps256".to_owned()),
+            private_key: Some(PathBuf::from("manifest.key")),
+            sign_cert: Some(PathBuf::from("manifest.pem")),
+            ta_url: Some("http://manifest.tsa".to_owned()),
+            ..Default::default()
+        };
+        let file = SignConfig {
+            private_key: Some(PathBuf::from("file.key")),
+            sign_cert: Some(PathBuf::from("file.pem")),
+            ..Default::default()
+        };
+        // the credential comes from one source, while other settings are merged
+        let config = file.or(manifest.clone());
+        assert_eq!(config.alg, None);
+        assert_eq!(config.private_key, Some(PathBuf::from("file.key")));
+        assert_eq!(config.sign_cert, Some(PathBuf::from("file.pem")));
+        assert_eq!(config.ta_url.as_deref(), Some("http://manifest.tsa"));
+
+        // setting only the algorithm doesn't keep the other source's key
+        let file = SignConfig {
+            alg: Some("es256".to_owned()),
+            ..Default::default()
+        };
+        let config = file.or(manifest.clone());
+        assert_eq!(config.alg.as_deref(), Some("es256"));
+        assert_eq!(config.private_key, None);
+        assert_eq!(config.sign_cert, None);
+
+        let config = SignConfig::default().or(manifest);
+        assert_eq!(config.alg.as_deref(), Some("ps256"));
+        assert_eq!(config.private_key, Some(PathBuf::from("manifest.key")));
+    }
+
     #[test]
     fn test_sign_default() {
         let sign_config = SignConfig::default();
 
-        let signer = sign_config.signer().expect("get signer");
+        let signer = sign_config
+            .signer(&KeyPassphraseArgs::default(), None)
+            .expect("get signer");
         assert_eq!(signer.alg(), SigningAlg::Es256);
     }
 
@@ -145,7 +362,9 @@ pub mod tests {
     fn test_sign_from() {
         let sign_config = SignConfig::default();
 
-        let signer = sign_config.signer().expect("get signer");
+        let signer = sign_config
+            .signer(&KeyPassphraseArgs::default(), None)
+            .expect("get signer");
         assert_eq!(signer.alg(), SigningAlg::Es256);
     }
 }
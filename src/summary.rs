@@ -0,0 +1,445 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+//! A plain language summary of the provenance of an asset, for readers who aren't familiar
+//! with the C2PA manifest format.
+
+use std::fmt::Write;
+
+use c2pa::{
+    assertions::Relationship,
+    validation_status::{self, ValidationStatus},
+    Ingredient, Manifest, Reader,
+};
+use openssl::{nid::Nid, x509::X509};
+use serde_json::Value;
+
+/// Describes a standard C2PA action in plain language.
+fn action_description(action: &str) -> String {
+    let description = match action {
+        "c2pa.color_adjustments" => "Adjusted the colors or exposure",
+        "c2pa.converted" => "Converted it to another format",
+        "c2pa.created" => "Created it",
+        "c2pa.cropped" => "Cropped it",
+        "c2pa.drawing" => "Drew on it",
+        "c2pa.edited" => "Edited it",
+        "c2pa.edited.metadata" => "Edited its metadata",
+        "c2pa.filtered" => "Applied a filter",
+        "c2pa.opened" => "Opened an existing file",
+        "c2pa.orientation" => "Rotated or flipped it",
+        "c2pa.placed" => "Placed other content into it",
+        "c2pa.published" => "Published it",
+        "c2pa.redacted" => "Removed part of its provenance",
+        "c2pa.removed" => "Removed content from it",
+        "c2pa.repackaged" => "Repackaged it without changing the content",
+        "c2pa.resized" => "Resized it",
+        "c2pa.transcoded" => "Re-encoded it without changing the content",
+        "c2pa.unknown" => "Made changes that weren't described",
+        "c2pa.watermarked" => "Added a watermark",
+        other => return format!("Performed the action \"{}\"", other),
+    };
+    description.to_owned()
+}
+
+/// Describes an IPTC digital source type, given by its URI, in plain language.
+fn source_type_description(uri: &str) -> String {
+    let name = uri.rsplit('/').next().unwrap_or(uri);
+    let description = match name {
+        "trainedAlgorithmicMedia" => "Generated by AI",
+        "compositeWithTrainedAlgorithmicMedia" => {
+            "Combines AI generated content with other content"
+        }
+        "algorithmicallyEnhanced" => "Enhanced by an algorithm, which may be AI",
+        "algorithmicMedia" => "Generated by an algorithm that isn't trained AI",
+        "compositeSynthetic" => "Combines synthetic content with other content",
+        "composite" => "Combines content from several sources",
+        "compositeCapture" => "Combines several camera captures",
+        "digitalCapture" => "Captured by a camera or recorder",
+        "digitalArt" => "Digital art made by a person",
+        "dataDrivenMedia" => "Generated from data, like a chart",
+        "humanEdits" => "Edited by a person",
+        "minorHumanEdits" => "Lightly edited by a person",
+        "negativeFilm" => "Scanned from a film negative",
+        "positiveFilm" => "Scanned from a film positive or slide",
+        "print" => "Scanned from a print",
+        "screenCapture" => "A screenshot or screen recording",
+        "virtualRecording" => "Recorded in a virtual environment",
+        _ => return format!("Source type \"{}\"", name),
+    };
+    description.to_owned()
+}
+
+/// Explains a validation status code in plain language, if it is a standard one.
+fn status_explanation(code: &str) -> Option<&'static str> {
+    Some(match code {
+        validation_status::CLAIM_MISSING => "The manifest's claim is missing.",
+        validation_status::CLAIM_MULTIPLE => "The manifest has more than one claim.",
+        validation_status::HARD_BINDINGS_MISSING => {
+            "The manifest isn't bound to the content, so it can't show that the content is unchanged."
+        }
+        validation_status::CLAIM_REQUIRED_MISSING => "The claim is missing required information.",
+        validation_status::CLAIM_CBOR_INVALID => "The claim is damaged and can't be read.",
+        validation_status::INGREDIENT_HASHEDURI_MISMATCH => {
+            "An ingredient's provenance was changed after it was added."
+        }
+        validation_status::CLAIM_SIGNATURE_MISSING => "The manifest isn't signed.",
+        validation_status::CLAIM_SIGNATURE_MISMATCH => {
+            "The signature doesn't match the manifest, so the manifest was changed after it was signed."
+        }
+        validation_status::MANIFEST_INACCESSIBLE => "The manifest couldn't be found or read.",
+        validation_status::MANIFEST_MULTIPLE_PARENTS => "The manifest has more than one parent.",
+        validation_status::MANIFEST_UPDATE_INVALID | validation_status::MANIFEST_UPDATE_WRONG_PARENTS => {
+            "The manifest is an update manifest that isn't built correctly."
+        }
+        validation_status::SIGNING_CREDENTIAL_UNTRUSTED => {
+            "The signing certificate isn't issued by anyone on the trust list."
+        }
+        validation_status::SIGNING_CREDENTIAL_INVALID => {
+            "The signing certificate isn't valid for signing content credentials."
+        }
+        validation_status::SIGNING_CREDENTIAL_REVOKED => "The signing certificate was revoked.",
+        validation_status::SIGNING_CREDENTIAL_EXPIRED => {
+            "The signing certificate had expired when the manifest was signed."
+        }
+        validation_status::TIMESTAMP_MISMATCH => "The time stamp doesn't match the signature.",
+        validation_status::TIMESTAMP_UNTRUSTED => {
+            "The time stamp comes from an authority that isn't trusted."
+        }
+        validation_status::TIMESTAMP_OUTSIDE_VALIDITY => {
+            "The time stamp is outside the period the signing certificate was valid."
+        }
+        validation_status::ASSERTION_HASHEDURI_MISMATCH => {
+            "Some information in the manifest was changed after it was signed."
+        }
+        validation_status::ASSERTION_MISSING | validation_status::ASSERTION_INACCESSIBLE => {
+            "Some information the manifest refers to is missing."
+        }
+        validation_status::ASSERTION_UNDECLARED => {
+            "The manifest holds information its signature doesn't cover."
+        }
+        validation_status::ASSERTION_NOT_REDACTED => {
+            "Information that should have been removed is still there."
+        }
+        validation_status::ASSERTION_SELF_REDACTED => "The manifest removes its own information.",
+        validation_status::ASSERTION_REQUIRED_MISSING => "Required information is missing.",
+        validation_status::ASSERTION_JSON_INVALID | validation_status::ASSERTION_CBOR_INVALID => {
+            "Some information in the manifest is damaged and can't be read."
+        }
+        validation_status::ACTION_ASSERTION_INGREDIENT_MISMATCH => {
+            "The actions refer to an ingredient that doesn't match."
+        }
+        validation_status::ACTION_ASSERTION_REDACTED => "The list of actions was removed.",
+        validation_status::ASSERTION_DATAHASH_MISMATCH
+        | validation_status::ASSERTION_BMFFHASH_MISMATCH
+        | validation_status::ASSERTION_BOXHASH_MISMATCH => {
+            "The content was changed after it was signed."
+        }
+        validation_status::ASSERTION_CLOUD_DATA_HARD_BINDING
+        | validation_status::ASSERTION_CLOUD_DATA_ACTIONS => {
+            "Information stored in the cloud isn't allowed to be."
+        }
+        validation_status::ALGORITHM_UNSUPPORTED => {
+            "The manifest uses an algorithm that isn't supported, so it can't be checked."
+        }
+        code if code.starts_with(validation_status::ASSERTION_BOXHASH_UNKNOWN) => {
+            "The file has parts that the manifest doesn't account for."
+        }
+        _ => return None,
+    })
+}
+
+// a failed status in plain language, falling back to the SDK's explanation
+fn explain(status: &ValidationStatus) -> String {
+    match (status_explanation(status.code()), status.explanation()) {
+        (Some(explanation), _) => explanation.to_owned(),
+        (None, Some(explanation)) => {
+            format!(
+                "The content credentials couldn't be validated: {}.",
+                explanation
+            )
+        }
+        (None, None) => "The content credentials couldn't be validated.".to_owned(),
+    }
+}
+
+// the failed statuses, leaving out those that report success, repeats of the same failure
+// and the general error the SDK adds when the signing certificate is untrusted
+fn failures(statuses: Option<&[ValidationStatus]>) -> Vec<&ValidationStatus> {
+    let mut seen = Vec::new();
+    statuses
+        .unwrap_or_default()
+        .iter()
+        .filter(|s| !s.passed())
+        .filter(|s| {
+            let key = (s.code(), s.url());
+            let repeat = seen.contains(&key);
+            seen.push(key);
+            !repeat
+        })
+        .filter(|s| {
+            s.code() != validation_status::GENERAL_ERROR
+                || !s
+                    .explanation()
+                    .is_some_and(|e| e.contains("CoseCertUntrusted"))
+        })
+        .collect()
+}
+
+// the common name of the signing certificate, or the organization that issued it
+fn signer_name(manifest: &Manifest) -> Option<String> {
+    let info = manifest.signature_info()?;
+    let common_name = X509::stack_from_pem(info.cert_chain().as_bytes())
+        .ok()
+        .and_then(|chain| {
+            let subject = chain.first()?.subject_name();
+            let entry = subject.entries_by_nid(Nid::COMMONNAME).next()?;
+            entry.data().as_utf8().ok().map(|cn| cn.to_string())
+        });
+    match (common_name, &info.issuer) {
+        (Some(cn), Some(issuer)) if &cn != issuer => Some(format!("{} ({})", cn, issuer)),
+        (Some(cn), _) => Some(cn),
+        (None, issuer) => issuer.clone(),
+    }
+}
+
+// a signing time like "October 1, 2024 at 12:00 UTC"
+fn signing_time(manifest: &Manifest) -> Option<String> {
+    let time = manifest.time()?;
+    Some(match chrono::DateTime::parse_from_rfc3339(&time) {
+        Ok(time) => time
+            .with_timezone(&chrono::Utc)
+            .format("%B %-d, %Y at %H:%M UTC")
+            .to_string(),
+        Err(_) => time,
+    })
+}
+
+// the applications that made the manifest, like "Photo Editor 1.0, c2patool 0.9.12"
+fn made_with(manifest: &Manifest) -> String {
+    match &manifest.claim_generator_info {
+        Some(info) if !info.is_empty() => info
+            .iter()
+            .map(|i| match &i.version {
+                Some(version) => format!("{} {}", i.name, version),
+                None => i.name.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => manifest.claim_generator().to_owned(),
+    }
+}
+
+// the actions of the manifest's actions assertions
+fn actions(manifest: &Manifest) -> Vec<Value> {
+    manifest
+        .assertions()
+        .iter()
+        .filter(|a| a.label().starts_with("c2pa.actions"))
+        .filter_map(|a| a.value().ok()?.get("actions")?.as_array().cloned())
+        .flatten()
+        .collect()
+}
+
+// an action in plain language, like "Edited it, using Photo Editor 1.0"
+fn describe_action(action: &Value) -> String {
+    let mut text = action_description(action["action"].as_str().unwrap_or_default());
+    let agent = match &action["softwareAgent"] {
+        Value::String(agent) => Some(agent.clone()),
+        Value::Object(agent) => agent
+            .get("name")
+            .and_then(|n| n.as_str())
+            .map(|name| match agent.get("version").and_then(|v| v.as_str()) {
+                Some(version) => format!("{} {}", name, version),
+                None => name.to_owned(),
+            }),
+        _ => None,
+    };
+    if let Some(agent) = agent {
+        let _ = write!(text, ", using {}", agent);
+    }
+    if let Some(description) = action["parameters"]["description"].as_str() {
+        let _ = write!(text, ": {}", description);
+    }
+    text
+}
+
+// how an ingredient was used
+fn relationship(ingredient: &Ingredient) -> &'static str {
+    match ingredient.relationship() {
+        Relationship::ParentOf => "the original it was made from",
+        Relationship::ComponentOf => "a component",
+        Relationship::InputTo => "an input",
+    }
+}
+
+// the verdict on an ingredient's own content credentials
+fn ingredient_verdict(reader: &Reader, ingredient: &Ingredient) -> String {
+    let failures = failures(ingredient.validation_status());
+    if !failures.is_empty() {
+        let problems: Vec<String> = failures.iter().map(|s| explain(s)).collect();
+        return format!("has problems. {}", problems.join(" "));
+    }
+    match ingredient.active_manifest() {
+        Some(label) => match reader.get_manifest(label).and_then(signer_name) {
+            Some(signer) => format!("valid content credentials, signed by {}", signer),
+            None => "valid content credentials".to_owned(),
+        },
+        None => "no content credentials".to_owned(),
+    }
+}
+
+/// Summarizes the provenance of the active manifest of `reader` in plain language.
+///
+/// `trust_checked` tells whether the signing certificate was checked against trust anchors.
+pub fn summary(reader: &Reader, trust_checked: bool) -> String {
+    let Some(manifest) = reader.active_manifest() else {
+        return "This file has no content credentials.\n".to_owned();
+    };
+    let mut out = String::new();
+    let failures = failures(reader.validation_status());
+    let untrusted = failures
+        .iter()
+        .any(|s| s.code() == validation_status::SIGNING_CREDENTIAL_UNTRUSTED);
+    let invalid = failures
+        .iter()
+        .any(|s| s.code() != validation_status::SIGNING_CREDENTIAL_UNTRUSTED);
+
+    let _ = writeln!(
+        out,
+        "Content credentials for {}",
+        manifest.title().unwrap_or("this file")
+    );
+    let _ = writeln!(
+        out,
+        "  Signed by: {}",
+        signer_name(manifest).unwrap_or_else(|| "unknown".to_owned())
+    );
+    let _ = writeln!(
+        out,
+        "  Signed on: {}",
+        signing_time(manifest).unwrap_or_else(|| "not recorded".to_owned())
+    );
+    let _ = writeln!(out, "  Made with: {}", made_with(manifest));
+    let verdict = if invalid {
+        "Not valid. The content credentials have problems, listed below."
+    } else if untrusted {
+        "Valid, but the signer isn't on the trust list."
+    } else if trust_checked {
+        "Valid, and signed by a trusted signer."
+    } else {
+        "Valid. Whether the signer is trusted wasn't checked; use the trust subcommand to check it."
+    };
+    let _ = writeln!(out, "  Verdict:   {}", verdict);
+
+    let actions = actions(manifest);
+    let mut source_types: Vec<&str> = actions
+        .iter()
+        .filter_map(|a| a["digitalSourceType"].as_str())
+        .collect();
+    source_types.dedup();
+    out.push_str("\nHow it was made:\n");
+    if source_types.is_empty() {
+        out.push_str("  Source: not stated\n");
+    }
+    for source_type in source_types {
+        let _ = writeln!(out, "  Source: {}", source_type_description(source_type));
+    }
+    if actions.is_empty() {
+        out.push_str("  No actions were recorded.\n");
+    }
+    for action in &actions {
+        let _ = writeln!(out, "  - {}", describe_action(action));
+    }
+
+    if !manifest.ingredients().is_empty() {
+        out.push_str("\nIngredients:\n");
+        for ingredient in manifest.ingredients() {
+            let _ = writeln!(
+                out,
+                "  - \"{}\", {}: {}",
+                ingredient.title(),
+                relationship(ingredient),
+                ingredient_verdict(reader, ingredient)
+            );
+        }
+    }
+
+    if !failures.is_empty() {
+        out.push_str("\nProblems:\n");
+        for status in failures {
+            let _ = writeln!(out, "  - {} ({})", explain(status), status.code());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_descriptions() {
+        assert_eq!(action_description("c2pa.cropped"), "Cropped it");
+        assert_eq!(
+            action_description("com.example.sharpen"),
+            "Performed the action \"com.example.sharpen\""
+        );
+        assert_eq!(
+            source_type_description(
+                "http://cv.iptc.org/newscodes/digitalsourcetype/trainedAlgorithmicMedia"
+            ),
+            "Generated by AI"
+        );
+        assert_eq!(
+            status_explanation(validation_status::ASSERTION_DATAHASH_MISMATCH),
+            Some("The content was changed after it was signed.")
+        );
+        assert_eq!(
+            status_explanation("assertion.boxesHash.unknownBox"),
+            Some("The file has parts that the manifest doesn't account for.")
+        );
+        assert_eq!(status_explanation("general.error"), None);
+    }
+
+    #[test]
+    fn test_describe_action() {
+        let action = json!({
+            "action": "c2pa.edited",
+            "softwareAgent": {"name": "Photo Editor", "version": "1.0"},
+            "parameters": {"description": "removed a tree"}
+        });
+        assert_eq!(
+            describe_action(&action),
+            "Edited it, using Photo Editor 1.0: removed a tree"
+        );
+        let action = json!({"action": "c2pa.opened", "softwareAgent": "Viewer"});
+        assert_eq!(
+            describe_action(&action),
+            "Opened an existing file, using Viewer"
+        );
+    }
+
+    #[test]
+    fn test_summary_of_sample() {
+        let reader = Reader::from_file("tests/fixtures/C.jpg").unwrap();
+        let summary = summary(&reader, false);
+        assert!(summary.starts_with("Content credentials for C.jpg\n"));
+        assert!(summary.contains("  Signed by: C2PA Signer (C2PA Test Signing Cert)\n"));
+        assert!(summary.contains("  Made with: make_test_images/0.6.1 c2pa-rs/0.6.1\n"));
+        assert!(summary.contains("wasn't checked"));
+        assert!(!summary.contains("Problems:"));
+    }
+}
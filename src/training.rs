@@ -0,0 +1,288 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+};
+
+use anyhow::{bail, Context, Result};
+use c2pa::{Builder, Manifest, Reader};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Label of the C2PA training and data mining assertion.
+pub const TRAINING_MINING: &str = "c2pa.training-mining";
+
+pub const AI_GENERATIVE_TRAINING: &str = "c2pa.ai_generative_training";
+pub const AI_INFERENCE: &str = "c2pa.ai_inference";
+pub const AI_TRAINING: &str = "c2pa.ai_training";
+pub const DATA_MINING: &str = "c2pa.data_mining";
+
+/// The permission given for a training or mining entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TrainingMiningUse {
+    Allowed,
+    NotAllowed,
+    Constrained,
+}
+
+impl fmt::Display for TrainingMiningUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrainingMiningUse::Allowed => f.write_str("allowed"),
+            TrainingMiningUse::NotAllowed => f.write_str("notAllowed"),
+            TrainingMiningUse::Constrained => f.write_str("constrained"),
+        }
+    }
+}
+
+/// A single entry of a `c2pa.training-mining` assertion.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TrainingMiningEntry {
+    #[serde(rename = "use")]
+    pub use_: TrainingMiningUse,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constraint_info: Option<String>,
+}
+
+impl fmt::Display for TrainingMiningEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.constraint_info {
+            Some(info) => write!(f, "{} ({})", self.use_, info),
+            None => write!(f, "{}", self.use_),
+        }
+    }
+}
+
+/// Parses an entry given as `allowed`, `notAllowed` or `constrained[:<constraint info>]`.
+pub fn parse_entry(s: &str) -> Result<TrainingMiningEntry> {
+    let (use_str, constraint_info) = match s.split_once(':') {
+        Some((u, info)) => (u, Some(info.to_owned())),
+        None => (s, None),
+    };
+    let use_ = match use_str {
+        "allowed" => TrainingMiningUse::Allowed,
+        "notAllowed" | "not_allowed" => TrainingMiningUse::NotAllowed,
+        "constrained" => TrainingMiningUse::Constrained,
+        _ => bail!("expected one of allowed, notAllowed or constrained[:<info>]"),
+    };
+    if constraint_info.is_some() && use_ != TrainingMiningUse::Constrained {
+        bail!("constraint info is only valid with constrained");
+    }
+    Ok(TrainingMiningEntry {
+        use_,
+        constraint_info,
+    })
+}
+
+/// Command line shortcuts for the entries of a `c2pa.training-mining` assertion.
+///
+/// Each value is one of `allowed`, `notAllowed` or `constrained[:<constraint info>]`.
+#[derive(Args, Debug, Default)]
+pub struct TrainingMiningArgs {
+    /// Set the c2pa.ai_generative_training entry.
+    #[clap(long, value_parser = parse_entry)]
+    pub ai_generative_training: Option<TrainingMiningEntry>,
+
+    /// Set the c2pa.ai_inference entry.
+    #[clap(long, value_parser = parse_entry)]
+    pub ai_inference: Option<TrainingMiningEntry>,
+
+    /// Set the c2pa.ai_training entry.
+    #[clap(long, value_parser = parse_entry)]
+    pub ai_training: Option<TrainingMiningEntry>,
+
+    /// Set the c2pa.data_mining entry.
+    #[clap(long, value_parser = parse_entry)]
+    pub data_mining: Option<TrainingMiningEntry>,
+}
+
+impl TrainingMiningArgs {
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    fn entries(&self) -> Vec<(&'static str, &TrainingMiningEntry)> {
+        [
+            (AI_GENERATIVE_TRAINING, &self.ai_generative_training),
+            (AI_INFERENCE, &self.ai_inference),
+            (AI_TRAINING, &self.ai_training),
+            (DATA_MINING, &self.data_mining),
+        ]
+        .iter()
+        .filter_map(|(key, entry)| entry.as_ref().map(|e| (*key, e)))
+        .collect()
+    }
+
+    /// Merges the entries into the builder's `c2pa.training-mining` assertion,
+    /// creating the assertion if the manifest definition does not have one.
+    pub fn merge_into(&self, builder: &mut Builder) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let assertions = &mut builder.definition.assertions;
+        let (index, mut data) = match assertions.iter().position(|a| a.label == TRAINING_MINING) {
+            Some(index) => {
+                let existing = serde_json::to_value(&assertions[index])?;
+                (Some(index), existing["data"].clone())
+            }
+            None => (None, json!({})),
+        };
+
+        let entries = data
+            .as_object_mut()
+            .context("training-mining assertion data must be an object")?
+            .entry("entries")
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .context("training-mining entries must be an object")?;
+        for (key, entry) in self.entries() {
+            entries.insert(key.to_owned(), serde_json::to_value(entry)?);
+        }
+
+        let assertion = serde_json::from_value(json!({ "label": TRAINING_MINING, "data": data }))?;
+        match index {
+            Some(index) => assertions[index] = assertion,
+            None => assertions.push(assertion),
+        }
+        Ok(())
+    }
+}
+
+type Entries = BTreeMap<String, TrainingMiningEntry>;
+
+// reads the training-mining entries declared in a manifest, if any
+fn manifest_entries(manifest: &Manifest) -> Option<Entries> {
+    let assertion = manifest
+        .assertions()
+        .iter()
+        .find(|a| a.label() == TRAINING_MINING)?;
+    let entries = assertion.value().ok()?.get("entries")?.clone();
+    serde_json::from_value::<BTreeMap<String, Value>>(entries)
+        .ok()
+        .map(|entries| {
+            entries
+                .into_iter()
+                .filter_map(|(k, v)| serde_json::from_value(v).ok().map(|e| (k, e)))
+                .collect()
+        })
+}
+
+fn write_entries(out: &mut String, heading: &str, entries: &Entries) -> fmt::Result {
+    writeln!(out, "  {heading}:")?;
+    if entries.is_empty() {
+        writeln!(out, "    (none declared)")?;
+    }
+    for (key, entry) in entries {
+        writeln!(out, "    {key}: {entry}")?;
+    }
+    Ok(())
+}
+
+/// Summarizes the training and mining preferences declared by the active manifest and its ingredients.
+///
+/// Returns `None` if no manifest in the store declares any preferences.
+pub fn report(reader: &Reader) -> Option<String> {
+    if !reader
+        .iter_manifests()
+        .any(|m| manifest_entries(m).is_some())
+    {
+        return None;
+    }
+    let label = reader.active_label()?;
+    let manifest = reader.active_manifest()?;
+
+    let mut out = String::from("Training and mining preferences:\n");
+    let entries = manifest_entries(manifest).unwrap_or_default();
+    write_entries(&mut out, &format!("Active manifest {label}"), &entries).ok()?;
+
+    for ingredient in manifest.ingredients() {
+        if let Some(ingredient_label) = ingredient.active_manifest() {
+            let entries = reader
+                .get_manifest(ingredient_label)
+                .and_then(manifest_entries)
+                .unwrap_or_default();
+            let heading = format!("Ingredient \"{}\" {}", ingredient.title(), ingredient_label);
+            write_entries(&mut out, &heading, &entries).ok()?;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    #[test]
+    fn test_parse_entry() {
+        let entry = parse_entry("notAllowed").unwrap();
+        assert_eq!(entry.use_, TrainingMiningUse::NotAllowed);
+        assert_eq!(entry.constraint_info, None);
+
+        let entry = parse_entry("constrained:https://example.com/terms").unwrap();
+        assert_eq!(entry.use_, TrainingMiningUse::Constrained);
+        assert_eq!(
+            entry.constraint_info.as_deref(),
+            Some("https://example.com/terms")
+        );
+
+        assert!(parse_entry("maybe").is_err());
+        assert!(parse_entry("allowed:some info").is_err());
+    }
+
+    #[test]
+    fn test_merge_into_existing_assertion() {
+        let json = include_str!("../tests/fixtures/do_not_train.json");
+        let mut builder = Builder::from_json(json).unwrap();
+        let args = TrainingMiningArgs {
+            ai_inference: Some(parse_entry("allowed").unwrap()),
+            ..Default::default()
+        };
+        args.merge_into(&mut builder).unwrap();
+
+        let assertions = &builder.definition.assertions;
+        assert_eq!(
+            assertions
+                .iter()
+                .filter(|a| a.label == TRAINING_MINING)
+                .count(),
+            1
+        );
+        let value = serde_json::to_value(&assertions[0]).unwrap();
+        let entries = &value["data"]["entries"];
+        assert_eq!(entries[AI_INFERENCE]["use"], "allowed");
+        assert_eq!(entries[AI_TRAINING]["use"], "notAllowed");
+    }
+
+    #[test]
+    fn test_merge_into_new_assertion() {
+        let mut builder = Builder::from_json("{}").unwrap();
+        let args = TrainingMiningArgs {
+            data_mining: Some(parse_entry("constrained:contact us").unwrap()),
+            ..Default::default()
+        };
+        args.merge_into(&mut builder).unwrap();
+
+        let value = serde_json::to_value(&builder.definition.assertions[0]).unwrap();
+        assert_eq!(value["label"], TRAINING_MINING);
+        assert_eq!(
+            value["data"]["entries"][DATA_MINING]["constraint_info"],
+            "contact us"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+// Copyright 2024 Adobe. All rights reserved.
+// This file is licensed to you under the Apache License,
+// Version 2.0 (http://www.apache.org/licenses/LICENSE-2.0)
+// or the MIT license (http://opensource.org/licenses/MIT),
+// at your option.
+// Unless required by applicable law or agreed to in writing,
+// this software is distributed on an "AS IS" BASIS, WITHOUT
+// WARRANTIES OR REPRESENTATIONS OF ANY KIND, either express or
+// implied. See the LICENSE-MIT and LICENSE-APACHE files for the
+// specific language governing permissions and limitations under
+// each license.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use c2pa::{Signer, SigningAlg};
+use clap::{Args, ValueEnum};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use x509_parser::der_parser::parse_der;
+
+use crate::signer::SignConfig;
+
+/// Default time to wait for each time stamp authority to respond.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What to do when none of the time stamp authorities respond.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TsaPolicy {
+    /// Stop with an error.
+    #[default]
+    Fail,
+    /// Sign without a time stamp.
+    Skip,
+}
+
+/// Time stamp authority options that override the signing settings.
+#[derive(Args, Clone, Debug, Default)]
+pub(crate) struct TsaArgs {
+    /// URL of a time stamp authority. Repeat it to list fallbacks, tried in order.
+    /// Replaces the ta_url and ta_urls of the signing settings.
+    #[clap(long = "tsa-url", value_name = "URL")]
+    pub tsa_urls: Vec<String>,
+
+    /// What to do when no time stamp authority responds: fail, or skip the time stamp.
+    #[clap(long = "tsa-policy", value_enum)]
+    pub tsa_policy: Option<TsaPolicy>,
+
+    /// Seconds to wait for each time stamp authority to respond. Default: 10.
+    #[clap(long = "tsa-timeout", value_name = "SECONDS")]
+    pub tsa_timeout: Option<u64>,
+}
+
+impl TsaArgs {
+    /// Returns the signing settings set by these options, to be merged with [SignConfig::or].
+    pub fn sign_config(&self) -> SignConfig {
+        let mut urls = self.tsa_urls.iter().cloned();
+        SignConfig {
+            ta_url: urls.next(),
+            ta_urls: (!self.tsa_urls.is_empty()).then(|| urls.collect()),
+            ta_policy: self.tsa_policy,
+            ta_timeout: self.tsa_timeout,
+            ..Default::default()
+        }
+    }
+}
+
+// the outcome of each time stamp request, for the report after signing
+enum Outcome {
+    Stamped { url: String, elapsed: Duration },
+    Skipped,
+}
+
+/// A [Signer] that gets time stamps from a list of time stamp authorities, trying each in
+/// turn until one responds, and records which one was used.
+pub(crate) struct TsaSigner<'a> {
+    signer: &'a dyn Signer,
+    urls: Vec<String>,
+    policy: TsaPolicy,
+    timeout: Duration,
+    outcomes: Mutex<Vec<Outcome>>,
+}
+
+impl<'a> TsaSigner<'a> {
+    pub fn new(signer: &'a dyn Signer, sign_config: &SignConfig) -> Self {
+        Self {
+            signer,
+            urls: sign_config.tsa_urls(),
+            policy: sign_config.ta_policy.unwrap_or_default(),
+            timeout: sign_config
+                .ta_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_TIMEOUT),
+            outcomes: Mutex::new(Vec::new()),
+        }
+    }
+
+    // requests a time stamp from one authority, returning the RFC 3161 response
+    fn request(&self, client: &Client, url: &str, body: &[u8]) -> Result<Vec<u8>> {
+        let mut request = client
+            .post(url)
+            .header("Content-Type", "application/timestamp-query")
+            .body(body.to_vec());
+        for (name, value) in self.signer.timestamp_request_headers().unwrap_or_default() {
+            request = request.header(name, value);
+        }
+        let response = request.send()?;
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get("Content-Type")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_owned();
+        let bytes = response.bytes()?.to_vec();
+        if !status.is_success() {
+            bail!("returned {}", status);
+        }
+        if !content_type.starts_with("application/timestamp-reply") {
+            bail!(
+                "returned {:?} instead of a time stamp response",
+                content_type
+            );
+        }
+        match response_status(&bytes) {
+            // granted, or granted with modifications
+            Some(0) | Some(1) => Ok(bytes),
+            Some(status) => bail!("refused the request with status {}", status),
+            None => bail!("returned an invalid time stamp response"),
+        }
+    }
+
+    fn time_stamp(&self, message: &[u8]) -> Result<Option<Vec<u8>>> {
+        let body = self.signer.timestamp_request_body(message)?;
+        let client = Client::builder().timeout(self.timeout).build()?;
+
+        let mut failures = Vec::new();
+        for url in &self.urls {
+            let start = Instant::now();
+            match self.request(&client, url, &body) {
+                Ok(response) => {
+                    self.record(Outcome::Stamped {
+                        url: url.clone(),
+                        elapsed: start.elapsed(),
+                    });
+                    return Ok(Some(response));
+                }
+                Err(e) => {
+                    log::warn!("Time stamp authority {} failed: {}", url, e);
+                    failures.push(format!("{}: {}", url, e));
+                }
+            }
+        }
+
+        match self.policy {
+            TsaPolicy::Skip => {
+                eprintln!(
+                    "Signing without a time stamp. No time stamp authority responded:\n  {}",
+                    failures.join("\n  ")
+                );
+                self.record(Outcome::Skipped);
+                Ok(None)
+            }
+            TsaPolicy::Fail => bail!(
+                "No time stamp authority responded. Use --tsa-policy skip to sign without a time stamp.\n  {}",
+                failures.join("\n  ")
+            ),
+        }
+    }
+
+    fn record(&self, outcome: Outcome) {
+        if let Ok(mut outcomes) = self.outcomes.lock() {
+            outcomes.push(outcome);
+        }
+    }
+
+    /// Describes the time stamp authorities used since the signer was created, if any.
+    pub fn report(&self) -> Option<String> {
+        let outcomes = self.outcomes.lock().ok()?;
+        let mut lines = Vec::new();
+        for url in &self.urls {
+            let times: Vec<Duration> = outcomes
+                .iter()
+                .filter_map(|o| match o {
+                    Outcome::Stamped { url: used, elapsed } if used == url => Some(*elapsed),
+                    _ => None,
+                })
+                .collect();
+            match times.as_slice() {
+                [] => {}
+                [elapsed] => lines.push(format!(
+                    "Time stamp from {} in {} ms",
+                    url,
+                    elapsed.as_millis()
+                )),
+                _ => lines.push(format!(
+                    "{} time stamps from {}, {} ms on average",
+                    times.len(),
+                    url,
+                    (times.iter().sum::<Duration>() / times.len() as u32).as_millis()
+                )),
+            }
+        }
+        let skipped = outcomes
+            .iter()
+            .filter(|o| matches!(o, Outcome::Skipped))
+            .count();
+        if skipped > 0 {
+            lines.push(format!("{} signature(s) without a time stamp", skipped));
+        }
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    /// Returns the time stamp authority that stamped the latest signature, if it has one.
+    pub fn last_url(&self) -> Option<String> {
+        match self.outcomes.lock().ok()?.last()? {
+            Outcome::Stamped { url, .. } => Some(url.clone()),
+            Outcome::Skipped => None,
+        }
+    }
+
+    /// Prints the [TsaSigner::report] to stderr.
+    pub fn print_report(&self) {
+        if let Some(report) = self.report() {
+            eprintln!("{}", report);
+        }
+    }
+}
+
+// reads the PKIStatus of a DER TimeStampResp
+fn response_status(der: &[u8]) -> Option<u32> {
+    let (_, response) = parse_der(der).ok()?;
+    let status_info = response.as_sequence().ok()?.first()?;
+    let status = status_info.as_sequence().ok()?.first()?;
+    status.as_u32().ok()
+}
+
+impl Signer for TsaSigner<'_> {
+    fn sign(&self, data: &[u8]) -> c2pa::Result<Vec<u8>> {
+        self.signer.sign(data)
+    }
+
+    fn alg(&self) -> SigningAlg {
+        self.signer.alg()
+    }
+
+    fn certs(&self) -> c2pa::Result<Vec<Vec<u8>>> {
+        self.signer.certs()
+    }
+
+    fn reserve_size(&self) -> usize {
+        self.signer.reserve_size()
+    }
+
+    fn time_authority_url(&self) -> Option<String> {
+        self.urls.first().cloned()
+    }
+
+    fn timestamp_request_headers(&self) -> Option<Vec<(String, String)>> {
+        self.signer.timestamp_request_headers()
+    }
+
+    fn timestamp_request_body(&self, message: &[u8]) -> c2pa::Result<Vec<u8>> {
+        self.signer.timestamp_request_body(message)
+    }
+
+    fn send_timestamp_request(&self, message: &[u8]) -> Option<c2pa::Result<Vec<u8>>> {
+        if self.urls.is_empty() {
+            return None;
+        }
+        self.time_stamp(message)
+            .map_err(|e| c2pa::Error::OtherError(e.into()))
+            .transpose()
+    }
+
+    fn ocsp_val(&self) -> Option<Vec<u8>> {
+        self.signer.ocsp_val()
+    }
+
+    fn direct_cose_handling(&self) -> bool {
+        self.signer.direct_cose_handling()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use httpmock::prelude::*;
+
+    use super::*;
+    use crate::private_key::KeyPassphraseArgs;
+
+    // a granted TimeStampResp with no token, enough for the status check
+    const GRANTED: &[u8] = &[0x30, 0x05, 0x30, 0x03, 0x02, 0x01, 0x00];
+    const REJECTED: &[u8] = &[0x30, 0x05, 0x30, 0x03, 0x02, 0x01, 0x02];
+
+    fn local_signer() -> Box<dyn Signer> {
+        SignConfig {
+            private_key: Some("sample/es256_private.key".into()),
+            sign_cert: Some("sample/es256_certs.pem".into()),
+            ..Default::default()
+        }
+        .signer(&KeyPassphraseArgs::default(), None)
+        .unwrap()
+    }
+
+    fn tsa_config(urls: Vec<String>, policy: TsaPolicy) -> SignConfig {
+        TsaArgs {
+            tsa_urls: urls,
+            tsa_policy: Some(policy),
+            tsa_timeout: None,
+        }
+        .sign_config()
+    }
+
+    fn mock_tsa<'a>(server: &'a MockServer, status: u16, body: &[u8]) -> httpmock::Mock<'a> {
+        server.mock(|when, then| {
+            when.method(POST)
+                .path("/tsa")
+                .header("content-type", "application/timestamp-query");
+            then.status(status)
+                .header("content-type", "application/timestamp-reply")
+                .body(body);
+        })
+    }
+
+    #[test]
+    fn test_falls_back_to_next_tsa() {
+        let down = MockServer::start();
+        let down_mock = mock_tsa(&down, 503, b"");
+        let rejecting = MockServer::start();
+        let rejecting_mock = mock_tsa(&rejecting, 200, REJECTED);
+        let up = MockServer::start();
+        let up_mock = mock_tsa(&up, 200, GRANTED);
+
+        let signer = local_signer();
+        let urls = vec![down.url("/tsa"), rejecting.url("/tsa"), up.url("/tsa")];
+        let tsa = TsaSigner::new(signer.as_ref(), &tsa_config(urls, TsaPolicy::Fail));
+        let response = tsa.send_timestamp_request(b"claim").unwrap().unwrap();
+        assert_eq!(response, GRANTED);
+        down_mock.assert();
+        rejecting_mock.assert();
+        up_mock.assert();
+
+        let report = tsa.report().unwrap();
+        assert!(report.starts_with(&format!("Time stamp from {} in", up.url("/tsa"))));
+    }
+
+    #[test]
+    fn test_policy_when_no_tsa_responds() {
+        let down = MockServer::start();
+        mock_tsa(&down, 500, b"");
+        let signer = local_signer();
+
+        let config = tsa_config(vec![down.url("/tsa")], TsaPolicy::Fail);
+        let tsa = TsaSigner::new(signer.as_ref(), &config);
+        let error = tsa.send_timestamp_request(b"claim").unwrap().unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("No time stamp authority responded"));
+        assert!(error.to_string().contains("500"));
+
+        let config = tsa_config(vec![down.url("/tsa")], TsaPolicy::Skip);
+        let tsa = TsaSigner::new(signer.as_ref(), &config);
+        assert!(tsa.send_timestamp_request(b"claim").is_none());
+        assert_eq!(tsa.report().unwrap(), "1 signature(s) without a time stamp");
+    }
+
+    #[test]
+    fn test_tsa_timeout() {
+        let slow = MockServer::start();
+        slow.mock(|when, then| {
+            when.method(POST).path("/tsa");
+            then.status(200)
+                .header("content-type", "application/timestamp-reply")
+                .body(GRANTED)
+                .delay(Duration::from_secs(5));
+        });
+        let signer = local_signer();
+        let mut tsa = TsaSigner::new(
+            signer.as_ref(),
+            &tsa_config(vec![slow.url("/tsa")], TsaPolicy::Fail),
+        );
+        tsa.timeout = Duration::from_millis(200);
+        let start = Instant::now();
+        assert!(tsa.send_timestamp_request(b"claim").unwrap().is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_no_tsa() {
+        let signer = local_signer();
+        let tsa = TsaSigner::new(signer.as_ref(), &SignConfig::default());
+        assert!(tsa.send_timestamp_request(b"claim").is_none());
+        assert!(tsa.report().is_none());
+    }
+}
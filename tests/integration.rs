@@ -11,7 +11,12 @@
 // specific language governing permissions and limitations under
 // each license.
 
-use std::{error::Error, fs, path::PathBuf, process::Command};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 // Add methods on commands
 use assert_cmd::prelude::*;
@@ -199,3 +204,1587 @@ fn tool_info() -> Result<(), Box<dyn Error>> {
         .stdout(str::contains("Manifest store size = 51217"));
     Ok(())
 }
+
+#[test]
+// c2patool sample/image.jpg -c '{}' -o out.jpg --ai-training notAllowed --data-mining "constrained:ask first"
+fn tool_sign_training_mining_shortcuts() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let output = tempdir.path().join("out.jpg");
+    // resources for a manifest given with -c are written to the current folder
+    Command::cargo_bin("c2patool")?
+        .current_dir(tempdir.path())
+        .arg(fixture_path("earth_apollo17.jpg"))
+        .arg("-c")
+        .arg("{}")
+        .arg("-o")
+        .arg(&output)
+        .arg("--ai-training")
+        .arg("notAllowed")
+        .arg("--data-mining")
+        .arg("constrained:ask first")
+        .assert()
+        .success()
+        .stdout(str::contains("c2pa.training-mining"))
+        .stderr(str::contains("Training and mining preferences:"))
+        .stderr(str::contains("c2pa.ai_training: notAllowed"))
+        .stderr(str::contains("c2pa.data_mining: constrained (ask first)"));
+
+    // the report on stdout stays valid JSON
+    let read = Command::cargo_bin("c2patool")?.arg(&output).output()?;
+    assert!(read.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&read.stdout)?;
+    assert!(report["manifests"].is_object());
+    assert!(String::from_utf8(read.stderr)?.contains("c2pa.ai_training: notAllowed"));
+    Ok(())
+}
+
+#[test]
+fn tool_training_mining_requires_manifest() -> Result<(), Box<dyn Error>> {
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("--ai-inference")
+        .arg("allowed")
+        .assert()
+        .failure()
+        .stderr(str::contains("Manifest definition required"));
+    Ok(())
+}
+
+const FRAGMENT_MANIFEST: &str =
+    r#"{"assertions": [{"label": "org.contentauth.test", "data": {"my_key": "whatever I want"}}]}"#;
+
+// signs the fragmented fixtures into the output folder
+fn sign_fragment_fixtures(output: &Path) -> Result<(), Box<dyn Error>> {
+    let init_glob = fixture_path("fragmented").join("*/init.mp4");
+    Command::cargo_bin("c2patool")?
+        .arg(init_glob)
+        .arg("-c")
+        .arg(FRAGMENT_MANIFEST)
+        .arg("-o")
+        .arg(output)
+        .arg("fragment")
+        .arg("--fragments_glob")
+        .arg("seg-*.m4s")
+        .assert()
+        .success();
+    Ok(())
+}
+
+// overwrites the last bytes of a fragment's media data
+fn tamper(path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut data = fs::read(path)?;
+    let len = data.len();
+    data[len - 2..].copy_from_slice(b"XX");
+    fs::write(path, data)?;
+    Ok(())
+}
+
+#[test]
+fn tool_fragment_sign_and_verify() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    sign_fragment_fixtures(tempdir.path())?;
+
+    Command::cargo_bin("c2patool")?
+        .arg(tempdir.path().join("video/init.mp4"))
+        .arg("fragment")
+        .arg("--fragments_glob")
+        .arg("seg-*.m4s")
+        .assert()
+        .success()
+        .stdout(str::contains("my_key"));
+    Ok(())
+}
+
+#[test]
+fn tool_fragment_verify_reports_every_failure() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    sign_fragment_fixtures(tempdir.path())?;
+
+    // make a second rendition and damage a fragment in each
+    let video = tempdir.path().join("video");
+    let audio = tempdir.path().join("audio");
+    fs::create_dir(&audio)?;
+    for entry in fs::read_dir(&video)? {
+        let entry = entry?;
+        fs::copy(entry.path(), audio.join(entry.file_name()))?;
+    }
+    tamper(&video.join("seg-2.m4s"))?;
+    tamper(&audio.join("seg-3.m4s"))?;
+
+    Command::cargo_bin("c2patool")?
+        .arg(tempdir.path().join("*/init.mp4"))
+        .arg("fragment")
+        .arg("--fragments_glob")
+        .arg("seg-*.m4s")
+        .assert()
+        .failure()
+        .stderr(str::contains(
+            "video/seg-2.m4s\": assertion.bmffHash.mismatch",
+        ))
+        .stderr(str::contains(
+            "audio/seg-3.m4s\": assertion.bmffHash.mismatch",
+        ))
+        .stderr(str::contains("seg-1.m4s").not())
+        .stderr(str::contains("2 of 2 init manifests failed validation"));
+    Ok(())
+}
+
+#[test]
+fn tool_fragment_verify_fails_without_matches() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    Command::cargo_bin("c2patool")?
+        .arg(tempdir.path().join("*/init.mp4"))
+        .arg("fragment")
+        .arg("--fragments_glob")
+        .arg("seg-*.m4s")
+        .assert()
+        .failure()
+        .stdout(str::is_empty())
+        .stderr(str::contains("No init segments found"));
+    Ok(())
+}
+
+#[test]
+// c2patool -c '{...}' -o out tests/fixtures/fragmented/master.m3u8 fragment
+fn tool_fragment_sign_and_verify_hls() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path("fragmented/master.m3u8"))
+        .arg("-c")
+        .arg(FRAGMENT_MANIFEST)
+        .arg("-o")
+        .arg(tempdir.path())
+        .arg("fragment")
+        .assert()
+        .success()
+        .stdout(str::contains("Writing playlist"));
+
+    let media_playlist = fs::read_to_string(tempdir.path().join("video/video.m3u8"))?;
+    assert!(media_playlist.contains("#EXT-X-MAP:URI=\"init.mp4\""));
+    assert!(tempdir.path().join("video/seg-3.m4s").exists());
+
+    Command::cargo_bin("c2patool")?
+        .arg(tempdir.path().join("master.m3u8"))
+        .arg("fragment")
+        .assert()
+        .success()
+        .stdout(str::contains("my_key"));
+
+    // existing playlists are only replaced with --force, and checked before signing
+    let sign_again = || -> Result<Command, Box<dyn Error>> {
+        let mut command = Command::cargo_bin("c2patool")?;
+        command
+            .arg(fixture_path("fragmented/master.m3u8"))
+            .arg("-c")
+            .arg(FRAGMENT_MANIFEST)
+            .arg("-o")
+            .arg(tempdir.path());
+        Ok(command)
+    };
+    sign_again()?
+        .arg("fragment")
+        .assert()
+        .failure()
+        .stdout(str::contains("Adding manifest").not())
+        .stderr(str::contains("already exists, use -f/force to force write"));
+    sign_again()?
+        .arg("-f")
+        .arg("fragment")
+        .assert()
+        .success()
+        .stdout(str::contains("Writing playlist"));
+    Ok(())
+}
+
+#[test]
+// c2patool -c '{...}' -o out tests/fixtures/fragmented/manifest.mpd fragment
+fn tool_fragment_sign_and_verify_dash() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path("fragmented/manifest.mpd"))
+        .arg("-c")
+        .arg(FRAGMENT_MANIFEST)
+        .arg("-o")
+        .arg(tempdir.path())
+        .arg("fragment")
+        .assert()
+        .success();
+
+    tamper(&tempdir.path().join("video/seg-1.m4s"))?;
+    Command::cargo_bin("c2patool")?
+        .arg(tempdir.path().join("manifest.mpd"))
+        .arg("fragment")
+        .assert()
+        .failure()
+        .stderr(str::contains(
+            "video/seg-1.m4s\": assertion.bmffHash.mismatch",
+        ));
+    Ok(())
+}
+
+// runs a watch that stops as soon as no more fragments arrive
+fn watch_fragments(source: &Path, output: &Path) -> assert_cmd::assert::Assert {
+    Command::cargo_bin("c2patool")
+        .expect("c2patool binary")
+        .arg(source.join("video/init.mp4"))
+        .arg("-c")
+        .arg(FRAGMENT_MANIFEST)
+        .arg("-o")
+        .arg(output)
+        .arg("fragment")
+        .arg("--fragments_glob")
+        .arg("seg-*.m4s")
+        .arg("--watch")
+        .arg("--poll_interval")
+        .arg("100")
+        .arg("--idle_timeout")
+        .arg("1")
+        .assert()
+}
+
+#[test]
+fn tool_fragment_watch_resumes_from_checkpoint() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let source = tempdir.path().join("source");
+    let output = tempdir.path().join("output");
+    let fixtures = fixture_path("fragmented/video");
+    fs::create_dir_all(source.join("video"))?;
+    for name in ["init.mp4", "seg-1.m4s", "seg-2.m4s"] {
+        fs::copy(fixtures.join(name), source.join("video").join(name))?;
+    }
+
+    watch_fragments(&source, &output)
+        .success()
+        .stdout(str::contains("Signed 2 new fragments"));
+    assert!(output.join(".c2patool_checkpoint.json").exists());
+
+    // a fragment that arrives after a restart is added to the rendition's init segment,
+    // which is signed again with the fragments already written
+    fs::copy(fixtures.join("seg-3.m4s"), source.join("video/seg-3.m4s"))?;
+    watch_fragments(&source, &output)
+        .success()
+        .stdout(str::contains("with 2 signed fragments"))
+        .stdout(str::contains("Signed 1 new fragments").and(str::contains("(3 total)")));
+    let files: Vec<_> = fs::read_dir(output.join("video"))?
+        .map(|e| e.map(|e| e.file_name()))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(files.len(), 4);
+    Command::cargo_bin("c2patool")?
+        .arg(output.join("video/init.mp4"))
+        .arg("fragment")
+        .arg("--fragments_glob")
+        .arg("seg-*.m4s")
+        .assert()
+        .success()
+        .stdout(str::contains("my_key"));
+
+    // the outputs of deleted fragments are removed, and the rest still validate
+    fs::remove_file(source.join("video/seg-1.m4s"))?;
+    watch_fragments(&source, &output).success();
+    assert!(!output.join("video/seg-1.m4s").exists());
+    assert!(output.join("video/seg-2.m4s").exists());
+    Command::cargo_bin("c2patool")?
+        .arg(output.join("video/init.mp4"))
+        .arg("fragment")
+        .arg("--fragments_glob")
+        .arg("seg-*.m4s")
+        .assert()
+        .success()
+        .stdout(str::contains("my_key"));
+    Ok(())
+}
+
+#[test]
+fn tool_read_sidecar_manifest() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let output = tempdir.path().join("out.jpg");
+    // resources for a manifest given with -c are written to the current folder
+    Command::cargo_bin("c2patool")?
+        .current_dir(tempdir.path())
+        .arg(fixture_path("earth_apollo17.jpg"))
+        .arg("-c")
+        .arg("{}")
+        .arg("-s")
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success()
+        .stderr(str::contains("Manifest binding: external"));
+
+    // the sidecar next to the asset is discovered automatically
+    Command::cargo_bin("c2patool")?
+        .arg(&output)
+        .assert()
+        .success()
+        .stderr(str::contains("Manifest binding: external"))
+        .stderr(str::contains("out.c2pa"));
+
+    // the report on stdout stays valid JSON
+    let read = Command::cargo_bin("c2patool")?.arg(&output).output()?;
+    let report: serde_json::Value = serde_json::from_slice(&read.stdout)?;
+    assert!(report["manifests"].is_object());
+
+    // or can be given explicitly
+    let moved = tempdir.path().join("moved.c2pa");
+    fs::rename(tempdir.path().join("out.c2pa"), &moved)?;
+    Command::cargo_bin("c2patool")?
+        .arg(&output)
+        .arg("--manifest-file")
+        .arg(&moved)
+        .assert()
+        .success()
+        .stderr(str::contains("moved.c2pa"))
+        .stdout(str::contains("mismatch").not());
+
+    // and is validated against the asset it is read with
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path("libpng-test.png"))
+        .arg("--manifest-file")
+        .arg(&moved)
+        .assert()
+        .success()
+        .stdout(str::contains("mismatch"));
+    Ok(())
+}
+
+#[test]
+fn tool_read_remote_manifest_offline() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let output = tempdir.path().join("out.jpg");
+    Command::cargo_bin("c2patool")?
+        .current_dir(tempdir.path())
+        .arg(fixture_path("earth_apollo17.jpg"))
+        .arg("-c")
+        .arg("{}")
+        .arg("-r")
+        .arg("https://cdn.example.com/manifests/out.c2pa")
+        .arg("-s")
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+
+    // serve the manifest store from a local folder
+    let manifests = tempdir.path().join("manifests");
+    fs::create_dir(&manifests)?;
+    fs::rename(tempdir.path().join("out.c2pa"), manifests.join("out.c2pa"))?;
+    Command::cargo_bin("c2patool")?
+        .arg(&output)
+        .arg("--remote-map")
+        .arg(format!(
+            "https://cdn.example.com/manifests/={}",
+            manifests.display()
+        ))
+        .assert()
+        .success()
+        .stderr(str::contains(
+            "Manifest binding: remote https://cdn.example.com/manifests/out.c2pa",
+        ));
+
+    // or from a local HTTP stand-in
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/manifests/out.c2pa");
+        then.status(200)
+            .body_from_file(manifests.join("out.c2pa").display().to_string());
+    });
+    Command::cargo_bin("c2patool")?
+        .arg(&output)
+        .arg("--info")
+        .arg("--remote-map")
+        .arg(format!("https://cdn.example.com/={}", server.url("/")))
+        .assert()
+        .success()
+        .stdout(str::contains(
+            "Cloud URL = https://cdn.example.com/manifests/out.c2pa",
+        ))
+        .stdout(str::contains("Validated"));
+    mock.assert();
+
+    // remote manifests without a mapping are not fetched
+    Command::cargo_bin("c2patool")?
+        .arg(&output)
+        .arg("--remote-map")
+        .arg(format!(
+            "https://other.example.com/={}",
+            manifests.display()
+        ))
+        .assert()
+        .failure()
+        .stderr(str::contains("No local mapping for remote manifest"));
+    Ok(())
+}
+
+// signs earth_apollo17.jpg with a remote manifest URL, publishing the store to `target`
+fn sign_and_publish(
+    tempdir: &Path,
+    remote_url: &str,
+    target: &str,
+) -> Result<assert_cmd::assert::Assert, Box<dyn Error>> {
+    Ok(Command::cargo_bin("c2patool")?
+        .current_dir(tempdir)
+        .arg(fixture_path("earth_apollo17.jpg"))
+        .arg("-c")
+        .arg("{}")
+        .arg("-r")
+        .arg(remote_url)
+        .arg("-s")
+        .arg("--publish")
+        .arg(target)
+        .arg("-o")
+        .arg(tempdir.join("out.jpg"))
+        .assert())
+}
+
+#[test]
+fn tool_publish_remote_manifest_to_folder() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let site = tempdir.path().join("site");
+    sign_and_publish(
+        tempdir.path(),
+        "https://cdn.example.com/manifests/out.c2pa",
+        site.to_str().unwrap(),
+    )?
+    .success()
+    .stderr(str::contains("Published manifest store to"))
+    .stderr(str::contains(
+        "Remote manifest URL: https://cdn.example.com/manifests/out.c2pa",
+    ));
+    assert_eq!(
+        fs::read(site.join("manifests/out.c2pa"))?,
+        fs::read(tempdir.path().join("out.c2pa"))?
+    );
+
+    // the published layout can be used to verify the asset offline
+    Command::cargo_bin("c2patool")?
+        .arg(tempdir.path().join("out.jpg"))
+        .arg("--remote-map")
+        .arg(format!("https://cdn.example.com/={}", site.display()))
+        .assert()
+        .success()
+        .stderr(str::contains("Manifest binding: remote"));
+    Ok(())
+}
+
+#[test]
+fn tool_publish_refuses_existing_store() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let site = tempdir.path().join("site");
+    fs::create_dir_all(site.join("manifests"))?;
+    fs::write(site.join("manifests/out.c2pa"), b"existing")?;
+    sign_and_publish(
+        tempdir.path(),
+        "https://cdn.example.com/manifests/out.c2pa",
+        site.to_str().unwrap(),
+    )?
+    .failure()
+    .stderr(str::contains("already exists, use -f/force"));
+    assert_eq!(fs::read(site.join("manifests/out.c2pa"))?, b"existing");
+
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(httpmock::Method::HEAD)
+            .path("/manifests/out.c2pa");
+        then.status(200);
+    });
+    let put = server.mock(|when, then| {
+        when.method(PUT);
+        then.status(201);
+    });
+    sign_and_publish(
+        tempfile::tempdir()?.path(),
+        "https://cdn.example.com/manifests/out.c2pa",
+        &server.base_url(),
+    )?
+    .failure()
+    .stderr(str::contains("already exists, use -f/force"));
+    put.assert_hits(0);
+    Ok(())
+}
+
+#[test]
+fn tool_publish_remote_manifest_with_put() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let server = MockServer::start();
+    let put = server.mock(|when, then| {
+        when.method(PUT)
+            .path("/upload/manifests/out.c2pa")
+            .header("content-type", "application/c2pa");
+        then.status(201);
+    });
+    // the store is checked at the remote URL, not where it was uploaded to
+    let get = server.mock(|when, then| {
+        when.method(GET).path("/manifests/out.c2pa");
+        then.status(200).body("not a manifest store");
+    });
+    let remote_url = server.url("/manifests/out.c2pa");
+    sign_and_publish(tempdir.path(), &remote_url, &server.url("/upload"))?
+        .failure()
+        .stderr(str::contains(format!(
+            "fetched back from {remote_url} does not match the one published"
+        )));
+    put.assert();
+    get.assert();
+    Ok(())
+}
+
+fn sample_es256_key() -> openssl::pkey::PKey<openssl::pkey::Private> {
+    openssl::pkey::PKey::private_key_from_pem(include_bytes!("../sample/es256_private.key"))
+        .expect("key")
+}
+
+// a DER ES256 signature made with the sample key, which a mock signing service returns for
+// any claim, so it is accepted as a signature but doesn't verify against the claim
+fn sample_es256_signature() -> Vec<u8> {
+    openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &sample_es256_key())
+        .and_then(|mut signer| signer.sign_oneshot_to_vec(b"not the claim"))
+        .expect("sign")
+}
+
+#[test]
+fn tool_sign_with_kms_mock() -> Result<(), Box<dyn Error>> {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+    let tempdir = tempfile::tempdir()?;
+    let output = tempdir.path().join("out.jpg");
+    let server = MockServer::start();
+    // the AWS KMS Sign API, for the `alias/c2pa` key
+    let sign = server.mock(|when, then| {
+        when.method(POST)
+            .header("x-amz-target", "TrentService.Sign")
+            .json_body_partial(
+                r#"{"KeyId": "alias/c2pa", "MessageType": "DIGEST", "SigningAlgorithm": "ECDSA_SHA_256"}"#,
+            );
+        then.status(200)
+            .header("content-type", "application/x-amz-json-1.1")
+            .json_body(serde_json::json!({
+                "KeyId": "alias/c2pa",
+                "Signature": BASE64.encode(sample_es256_signature()),
+                "SigningAlgorithm": "ECDSA_SHA_256",
+            }));
+    });
+    let config = serde_json::json!({
+        "alg": "es256",
+        "sign_cert": fs::canonicalize("sample/es256_certs.pem")?,
+        "signer": {
+            "type": "kms",
+            "provider": "aws",
+            "key_id": "alias/c2pa",
+            "region": "us-east-1",
+            "endpoint": server.base_url(),
+        },
+        "assertions": [],
+    });
+
+    // the signature from the service is checked against the claim before it is embedded
+    Command::cargo_bin("c2patool")?
+        .current_dir(tempdir.path())
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(config.to_string())
+        .arg("-o")
+        .arg(&output)
+        .arg("-f")
+        .env("AWS_ACCESS_KEY_ID", "test")
+        .env("AWS_SECRET_ACCESS_KEY", "test")
+        .assert()
+        .failure()
+        .stderr(str::contains(
+            "The signature does not verify with the public key",
+        ));
+    sign.assert();
+
+    // a key the service doesn't have is reported with the service's error
+    server.mock(|when, then| {
+        when.method(POST)
+            .json_body_partial(r#"{"KeyId": "alias/unknown"}"#);
+        then.status(400)
+            .header("content-type", "application/x-amz-json-1.1")
+            .body(r#"{"__type": "ValidationException"}"#);
+    });
+    let mut config = config;
+    config["signer"]["key_id"] = "alias/unknown".into();
+    Command::cargo_bin("c2patool")?
+        .current_dir(tempdir.path())
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(config.to_string())
+        .arg("-o")
+        .arg(tempdir.path().join("out_unknown.jpg"))
+        .env("AWS_ACCESS_KEY_ID", "test")
+        .env("AWS_SECRET_ACCESS_KEY", "test")
+        .assert()
+        .failure()
+        .stderr(str::contains("ValidationException"));
+    Ok(())
+}
+
+#[test]
+fn tool_sign_with_signer_url() -> Result<(), Box<dyn Error>> {
+    let server = MockServer::start();
+    let mut sign = server.mock(|when, then| {
+        when.method(POST)
+            .path("/sign")
+            .header("x-api-key", "secret")
+            .header("content-type", "application/octet-stream");
+        then.status(200).body(sample_es256_signature());
+    });
+    let url = server.url("/sign");
+    let tempdir = tempfile::tempdir()?;
+    let config = serde_json::json!({
+        "alg": "es256",
+        "sign_cert": fs::canonicalize("sample/es256_certs.pem")?,
+        "assertions": [],
+    })
+    .to_string();
+
+    // the signature from the service is checked against the claim before it is embedded
+    Command::cargo_bin("c2patool")?
+        .current_dir(tempdir.path())
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(&config)
+        .arg("-o")
+        .arg(tempdir.path().join("out.jpg"))
+        .arg("--signer-url")
+        .arg(&url)
+        .arg("--signer-header")
+        .arg("X-Api-Key: secret")
+        .assert()
+        .failure()
+        .stderr(str::contains(
+            "The signature does not verify with the public key",
+        ));
+    sign.assert();
+
+    sign.delete();
+    server.mock(|when, then| {
+        when.method(POST).path("/sign");
+        then.status(401).body("missing API key");
+    });
+    Command::cargo_bin("c2patool")?
+        .current_dir(tempdir.path())
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(&config)
+        .arg("-o")
+        .arg(tempdir.path().join("out2.jpg"))
+        .arg("--signer-url")
+        .arg(&url)
+        .assert()
+        .failure()
+        .stderr(str::contains("401 Unauthorized").and(str::contains("missing API key")));
+
+    // only one kind of external signer can be used
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(&config)
+        .arg("-o")
+        .arg(tempdir.path().join("out3.jpg"))
+        .arg("--signer-url")
+        .arg(&url)
+        .arg("--signer-path")
+        .arg("./signer")
+        .assert()
+        .failure()
+        .stderr(str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn tool_sign_with_signer_config_profile() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let signer_config = tempdir.path().join("signer.json");
+    let sample = fs::canonicalize("sample")?;
+    fs::write(
+        &signer_config,
+        serde_json::json!({
+            "alg": "es256",
+            "profiles": {
+                "sample": {
+                    "private_key": sample.join("es256_private.key"),
+                    "sign_cert": sample.join("es256_certs.pem"),
+                },
+            },
+        })
+        .to_string(),
+    )?;
+
+    // the manifest definition has no signing settings of its own
+    Command::cargo_bin("c2patool")?
+        .current_dir(tempdir.path())
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(r#"{"assertions": []}"#)
+        .arg("-o")
+        .arg(tempdir.path().join("out.jpg"))
+        .arg("--signer-config")
+        .arg(&signer_config)
+        .arg("--signer-profile")
+        .arg("sample")
+        .assert()
+        .success()
+        .stderr(str::contains("Using default private key").not());
+
+    Command::cargo_bin("c2patool")?
+        .current_dir(tempdir.path())
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(r#"{"assertions": []}"#)
+        .arg("-o")
+        .arg(tempdir.path().join("out2.jpg"))
+        .env("C2PATOOL_SIGNER_CONFIG", &signer_config)
+        .env("C2PATOOL_SIGNER_PROFILE", "missing")
+        .assert()
+        .failure()
+        .stderr(str::contains("Available profiles: sample"));
+    Ok(())
+}
+
+#[test]
+fn tool_sign_with_encrypted_private_key() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let key = openssl::pkey::PKey::private_key_from_pem(&fs::read("sample/es256_private.key")?)?;
+    let encrypted =
+        key.private_key_to_pem_pkcs8_passphrase(openssl::symm::Cipher::aes_256_cbc(), b"secret")?;
+    let key_path = tempdir.path().join("encrypted.key");
+    fs::write(&key_path, encrypted)?;
+    let manifest = serde_json::json!({
+        "private_key": key_path,
+        "sign_cert": fs::canonicalize("sample/es256_certs.pem")?,
+    })
+    .to_string();
+
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(&manifest)
+        .arg("-o")
+        .arg(tempdir.path().join("out.jpg"))
+        .arg("--key-passphrase-env")
+        .arg("C2PATOOL_TEST_PASSPHRASE")
+        .env("C2PATOOL_TEST_PASSPHRASE", "secret")
+        .assert()
+        .success();
+
+    // without a terminal to prompt on, the passphrase must be given
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(&manifest)
+        .arg("-o")
+        .arg(tempdir.path().join("out2.jpg"))
+        .assert()
+        .failure()
+        .stderr(str::contains("--key-passphrase-env"));
+    Ok(())
+}
+
+#[test]
+fn tool_sign_preflight_checks_sign_cert() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let sample = fs::canonicalize("sample")?;
+
+    // the ps256 key doesn't belong to the es256 certificate
+    let manifest = serde_json::json!({
+        "alg": "es256",
+        "private_key": sample.join("ps256.pem"),
+        "sign_cert": sample.join("es256_certs.pem"),
+    });
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(manifest.to_string())
+        .arg("-o")
+        .arg(tempdir.path().join("out.jpg"))
+        .assert()
+        .failure()
+        .stderr(str::contains(
+            "The private key does not match the certificate \"C2PA Signer\"",
+        ));
+
+    // a trust config that only allows document signing certificates
+    let trust_config = tempdir.path().join("store.cfg");
+    fs::write(
+        &trust_config,
+        "//id-kp-documentSigning\n1.3.6.1.5.5.7.3.36\n",
+    )?;
+    let manifest = serde_json::json!({
+        "alg": "es256",
+        "private_key": sample.join("es256_private.key"),
+        "sign_cert": sample.join("es256_certs.pem"),
+    });
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(manifest.to_string())
+        .arg("-o")
+        .arg(tempdir.path().join("out2.jpg"))
+        .arg("trust")
+        .arg("--trust_config")
+        .arg(&trust_config)
+        .assert()
+        .failure()
+        .stderr(str::contains(
+            "has extended key usages 1.3.6.1.5.5.7.3.4, but needs one of: 1.3.6.1.5.5.7.3.36",
+        ));
+    Ok(())
+}
+
+#[test]
+fn tool_keygen_certs_validate_with_trust_settings() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let certs = tempdir.path().join("certs");
+
+    Command::cargo_bin("c2patool")?
+        .arg(&certs)
+        .arg("keygen")
+        .arg("--alg")
+        .arg("es256,ed25519")
+        .assert()
+        .success()
+        .stdout(str::contains("\"alg\": \"ed25519\""))
+        .stdout(str::contains("--trust_anchors"));
+
+    let manifest = serde_json::json!({
+        "alg": "ed25519",
+        "private_key": certs.join("ed25519_private.key"),
+        "sign_cert": certs.join("ed25519_certs.pem"),
+    });
+    let output = tempdir.path().join("out.jpg");
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path("earth_apollo17.jpg"))
+        .arg("-c")
+        .arg(manifest.to_string())
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+
+    Command::cargo_bin("c2patool")?
+        .arg(&output)
+        .arg("trust")
+        .arg("--trust_anchors")
+        .arg(certs.join("trust_anchors.pem"))
+        .arg("--trust_config")
+        .arg(certs.join("store.cfg"))
+        .assert()
+        .success()
+        .stdout(str::contains("validation_status").not());
+
+    // the files aren't replaced without --force
+    Command::cargo_bin("c2patool")?
+        .arg(&certs)
+        .arg("keygen")
+        .arg("--alg")
+        .arg("es256")
+        .assert()
+        .failure()
+        .stderr(str::contains("already exists"));
+    Ok(())
+}
+
+// DER encodes `content` with `tag`
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let len = content.len();
+    let mut out = vec![tag];
+    match len {
+        0..=0x7f => out.push(len as u8),
+        0x80..=0xff => out.extend([0x81, len as u8]),
+        _ => out.extend([0x82, (len >> 8) as u8, len as u8]),
+    }
+    out.extend(content);
+    out
+}
+
+// splits the first DER element off `data`, returning it whole, its content and the rest
+fn der_split(data: &[u8]) -> (&[u8], &[u8], &[u8]) {
+    let (header, len) = match data[1] {
+        0x81 => (3, data[2] as usize),
+        0x82 => (4, (data[2] as usize) << 8 | data[3] as usize),
+        len => (2, len as usize),
+    };
+    let (element, rest) = data.split_at(header + len);
+    (element, &element[header..], rest)
+}
+
+// a minimal RFC 3161 time stamp authority, signing with a self-signed P-256 certificate
+fn start_tsa() -> String {
+    use openssl::{
+        asn1::{Asn1Integer, Asn1Time},
+        bn::BigNum,
+        ec::{EcGroup, EcKey},
+        ecdsa::EcdsaSig,
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::PKey,
+        sha::sha256,
+        x509::{extension::ExtendedKeyUsage, X509Name, X509},
+    };
+
+    const OID_SIGNED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+    const OID_TST_INFO: &[u8] = &[
+        0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x10, 0x01, 0x04,
+    ];
+    const OID_CONTENT_TYPE: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x03];
+    const OID_MESSAGE_DIGEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x04];
+    const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+    const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+    const OID_POLICY: &[u8] = &[0x2a, 0x03, 0x04];
+
+    let key = EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).expect("group"))
+        .and_then(PKey::from_ec_key)
+        .expect("key");
+    let cert = (|| {
+        let mut name = X509Name::builder()?;
+        name.append_entry_by_nid(Nid::COMMONNAME, "c2patool test TSA")?;
+        let name = name.build();
+        let mut builder = X509::builder()?;
+        builder.set_version(2)?;
+        let serial = BigNum::from_u32(1)?;
+        let serial = Asn1Integer::from_bn(&serial)?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(&name)?;
+        builder.set_pubkey(&key)?;
+        let not_before = Asn1Time::days_from_now(0)?;
+        let not_after = Asn1Time::days_from_now(1)?;
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+        builder.append_extension(ExtendedKeyUsage::new().critical().time_stamping().build()?)?;
+        builder.sign(&key, MessageDigest::sha256())?;
+        Ok::<_, openssl::error::ErrorStack>(builder.build())
+    })()
+    .expect("cert");
+    let ec_key = key.ec_key().expect("ec key");
+
+    serve_timestamps(move |body| {
+        // TimeStampReq: version, messageImprint, then optional reqPolicy, nonce and certReq
+        let (_, request, _) = der_split(&body);
+        let (_, _, rest) = der_split(request);
+        let (imprint, _, mut rest) = der_split(rest);
+        let mut nonce = None;
+        while !rest.is_empty() {
+            let (element, _, next) = der_split(rest);
+            if element[0] == 0x02 {
+                nonce = Some(element);
+            }
+            rest = next;
+        }
+
+        let gen_time = chrono::Utc::now().format("%Y%m%d%H%M%SZ").to_string();
+        let mut tst_info = der(0x02, &[1]);
+        tst_info.extend(der(0x06, OID_POLICY));
+        tst_info.extend(imprint);
+        tst_info.extend(der(0x02, &[1]));
+        tst_info.extend(der(0x18, gen_time.as_bytes()));
+        if let Some(nonce) = nonce {
+            tst_info.extend(nonce);
+        }
+        let tst_info = der(0x30, &tst_info);
+
+        let mut content_type = der(0x06, OID_CONTENT_TYPE);
+        content_type.extend(der(0x31, &der(0x06, OID_TST_INFO)));
+        let mut message_digest = der(0x06, OID_MESSAGE_DIGEST);
+        message_digest.extend(der(0x31, &der(0x04, &sha256(&tst_info))));
+        let mut attrs = der(0x30, &content_type);
+        attrs.extend(der(0x30, &message_digest));
+        let signature = EcdsaSig::sign(&sha256(&der(0x31, &attrs)), &ec_key)
+            .and_then(|s| s.to_der())
+            .expect("sign");
+
+        let mut issuer_and_serial = cert.issuer_name().to_der().expect("issuer");
+        issuer_and_serial.extend(der(0x02, &[1]));
+        let mut signer_info = der(0x02, &[1]);
+        signer_info.extend(der(0x30, &issuer_and_serial));
+        signer_info.extend(der(0x30, &der(0x06, OID_SHA256)));
+        signer_info.extend(der(0xa0, &attrs));
+        signer_info.extend(der(0x30, &der(0x06, OID_ECDSA_SHA256)));
+        signer_info.extend(der(0x04, &signature));
+
+        let mut encap_content = der(0x06, OID_TST_INFO);
+        encap_content.extend(der(0xa0, &der(0x04, &tst_info)));
+        let mut signed_data = der(0x02, &[3]);
+        signed_data.extend(der(0x31, &der(0x30, &der(0x06, OID_SHA256))));
+        signed_data.extend(der(0x30, &encap_content));
+        signed_data.extend(der(0xa0, &cert.to_der().expect("cert der")));
+        signed_data.extend(der(0x31, &der(0x30, &signer_info)));
+
+        let mut content_info = der(0x06, OID_SIGNED_DATA);
+        content_info.extend(der(0xa0, &der(0x30, &signed_data)));
+        let mut response = der(0x30, &der(0x02, &[0]));
+        response.extend(der(0x30, &content_info));
+        der(0x30, &response)
+    })
+}
+
+// answers time stamp requests on a local port with the response `handler` makes for each
+// request body, which a mock with fixed responses can't do as each request has its own imprint
+fn serve_timestamps<F>(handler: F) -> String
+where
+    F: Fn(Vec<u8>) -> Vec<u8> + Send + 'static,
+{
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let url = format!("http://{}", listener.local_addr().expect("addr"));
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.expect("connection");
+            let mut reader = BufReader::new(stream.try_clone().expect("clone"));
+            let mut request_line = String::new();
+            while reader.read_line(&mut request_line).unwrap_or(0) > 0 {
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).expect("header");
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap_or(0);
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).expect("body");
+                let response = handler(body);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/timestamp-reply\r\nContent-Length: {}\r\n\r\n",
+                    response.len()
+                )
+                .and_then(|_| stream.write_all(&response))
+                .expect("response");
+                request_line.clear();
+            }
+        }
+    });
+    url
+}
+
+#[test]
+fn tool_sign_falls_back_to_next_tsa() -> Result<(), Box<dyn Error>> {
+    // nothing listens on this port once the listener is dropped
+    let dead_url = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        format!("http://{}", listener.local_addr()?)
+    };
+    let tsa_url = start_tsa();
+    let tempdir = tempfile::tempdir()?;
+    let output = tempdir.path().join("out.jpg");
+
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-m")
+        .arg("sample/test.json")
+        .arg("-o")
+        .arg(&output)
+        .arg("--tsa-url")
+        .arg(&dead_url)
+        .arg("--tsa-url")
+        .arg(&tsa_url)
+        .arg("--tsa-timeout")
+        .arg("5")
+        .assert()
+        .success()
+        .stderr(str::contains(format!("Time stamp from {tsa_url}")));
+
+    // the time stamp from the stand-in validates
+    Command::cargo_bin("c2patool")?
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout(str::contains("\"time\""))
+        .stdout(str::contains("timeStamp").not());
+
+    // without a working time stamp authority, signing fails unless the policy is skip
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-m")
+        .arg("sample/test.json")
+        .arg("-o")
+        .arg(tempdir.path().join("fail.jpg"))
+        .arg("--tsa-url")
+        .arg(&dead_url)
+        .assert()
+        .failure()
+        .stderr(str::contains("No time stamp authority responded"));
+
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-m")
+        .arg("sample/test.json")
+        .arg("-o")
+        .arg(tempdir.path().join("skip.jpg"))
+        .arg("--tsa-url")
+        .arg(&dead_url)
+        .arg("--tsa-policy")
+        .arg("skip")
+        .assert()
+        .success()
+        .stderr(str::contains("Signing without a time stamp"));
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn tool_sign_appends_audit_records() -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tempdir = tempfile::tempdir()?;
+    let log = tempdir.path().join("audit.jsonl");
+
+    // embedded, with the sample signer
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(FRAGMENT_MANIFEST)
+        .arg("-o")
+        .arg(tempdir.path().join("embedded.jpg"))
+        .arg("--audit-log")
+        .arg(&log)
+        .assert()
+        .success();
+
+    // sidecar, with a signer process
+    let signer = tempdir.path().join("signer.sh");
+    fs::write(
+        &signer,
+        format!(
+            "#!/bin/sh\nexec openssl dgst -sha256 -sign {:?}\n",
+            fs::canonicalize("sample/es256_private.key")?
+        ),
+    )?;
+    fs::set_permissions(&signer, fs::Permissions::from_mode(0o755))?;
+    let config = serde_json::json!({
+        "alg": "es256",
+        "sign_cert": fs::canonicalize("sample/es256_certs.pem")?,
+        "assertions": [],
+    });
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(config.to_string())
+        .arg("-o")
+        .arg(tempdir.path().join("sidecar.jpg"))
+        .arg("--sidecar")
+        .arg("--signer-path")
+        .arg(&signer)
+        .env("C2PATOOL_AUDIT_LOG", &log)
+        .assert()
+        .success();
+
+    // fragmented
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path("fragmented").join("*/init.mp4"))
+        .arg("-c")
+        .arg(FRAGMENT_MANIFEST)
+        .arg("-o")
+        .arg(tempdir.path().join("fragmented"))
+        .arg("--audit-log")
+        .arg(&log)
+        .arg("fragment")
+        .arg("--fragments_glob")
+        .arg("seg-*.m4s")
+        .assert()
+        .success();
+
+    // remote, recorded even though publishing it fails
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(FRAGMENT_MANIFEST)
+        .arg("-o")
+        .arg(tempdir.path().join("remote.jpg"))
+        .arg("--remote")
+        .arg("https://cdn.example.com/remote.c2pa")
+        .arg("--publish")
+        .arg("http://127.0.0.1:1/")
+        .arg("--audit-log")
+        .arg(&log)
+        .assert()
+        .failure()
+        .stderr(str::contains("Failed to check for manifest store"));
+
+    let records: Vec<serde_json::Value> = fs::read_to_string(&log)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[3]["flow"], "remote");
+    for record in &records {
+        assert_eq!(record["tool"], "c2patool");
+        assert_eq!(record["alg"], "es256");
+        assert_eq!(record["output_sha256"].as_str().map(str::len), Some(64));
+        assert!(record["manifest_label"]
+            .as_str()
+            .is_some_and(|label| label.starts_with("urn:uuid:")));
+    }
+    // every signature is made with the sample certificate
+    assert_eq!(records[0]["cert_sha256"], records[1]["cert_sha256"]);
+
+    assert_eq!(records[0]["flow"], "embedded");
+    assert_eq!(records[0]["signer"], "local");
+    assert_eq!(records[1]["flow"], "sidecar");
+    assert_eq!(records[1]["signer"], "process");
+    assert_eq!(
+        records[1]["sidecar"],
+        serde_json::json!(tempdir.path().join("sidecar.c2pa"))
+    );
+    assert_eq!(records[2]["flow"], "fragment");
+    assert_eq!(records[2]["fragments"], 3);
+    Ok(())
+}
+
+#[test]
+fn tool_sign_records_claim_generators() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let version = env!("CARGO_PKG_VERSION");
+
+    // c2patool is recorded even when the manifest definition has no claim generator info
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path("earth_apollo17.jpg"))
+        .arg("-c")
+        .arg("{}")
+        .arg("-o")
+        .arg(tempdir.path().join("default.jpg"))
+        .assert()
+        .success()
+        .stdout(str::contains(format!(
+            "\"claim_generator\": \"c2patool/{version}\""
+        )));
+
+    let config = r#"{"claim_generator_info": [{"name": "Plugin", "version": "0.1"}]}"#;
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path("earth_apollo17.jpg"))
+        .arg("-c")
+        .arg(config)
+        .arg("-o")
+        .arg(tempdir.path().join("app.jpg"))
+        .arg("--app-name")
+        .arg("My App")
+        .arg("--app-version")
+        .arg("1.2")
+        .arg("--app-icon")
+        .arg(fixture_path("sample1.svg"))
+        .assert()
+        .success()
+        .stdout(str::contains(format!(
+            "\"claim_generator\": \"my_app/1.2 plugin/0.1 c2patool/{version}\""
+        )))
+        .stdout(str::contains("\"format\": \"image/svg+xml\""))
+        .stdout(str::contains("validation_status").not());
+
+    // the app version and icon need a name
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path("earth_apollo17.jpg"))
+        .arg("-c")
+        .arg("{}")
+        .arg("-o")
+        .arg(tempdir.path().join("no_name.jpg"))
+        .arg("--app-version")
+        .arg("1.2")
+        .assert()
+        .failure()
+        .stderr(str::contains("--app-name"));
+
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path("earth_apollo17.jpg"))
+        .arg("-o")
+        .arg(tempdir.path().join("no_manifest.jpg"))
+        .arg("--app-name")
+        .arg("MyApp")
+        .assert()
+        .failure()
+        .stderr(str::contains("Manifest definition required"));
+    Ok(())
+}
+
+#[test]
+fn tool_report_formats() -> Result<(), Box<dyn Error>> {
+    // the detailed report is real JSON
+    let output = Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-d")
+        .output()?;
+    assert!(output.status.success());
+    let detailed: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert!(detailed["manifests"].is_object());
+
+    // canonical JSON has the same content with sorted keys
+    let output = Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-d")
+        .arg("--format")
+        .arg("canonical-json")
+        .output()?;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&output.stdout)?,
+        detailed
+    );
+    let text = String::from_utf8(output.stdout)?;
+    assert!(text.find("\"active_manifest\"") < text.find("\"manifests\""));
+
+    // CBOR has the same report as JSON, with the assertions' data as stored
+    let json = Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .output()?;
+    let json: serde_json::Value = serde_json::from_slice(&json.stdout)?;
+    let cbor = Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("--format")
+        .arg("cbor")
+        .output()?;
+    let decoded: ciborium::Value = ciborium::from_reader(cbor.stdout.as_slice())?;
+    let member = |value: &ciborium::Value, key: &str| {
+        value
+            .as_map()
+            .and_then(|map| map.iter().find(|(k, _)| k.as_text() == Some(key)))
+            .map(|(_, v)| v.clone())
+            .unwrap_or(ciborium::Value::Null)
+    };
+    let label = json["active_manifest"].as_str().unwrap_or_default();
+    let manifest = member(&member(&decoded, "manifests"), label);
+    assert_eq!(
+        member(&manifest, "title").as_text(),
+        json["manifests"][label]["title"].as_str()
+    );
+    let actions = Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("extract")
+        .arg("c2pa.actions")
+        .output()?;
+    let assertions = member(&manifest, "assertions");
+    let stored = assertions
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|a| member(a, "label").as_text() == Some("c2pa.actions"))
+        .map(|a| member(a, "data"));
+    assert_eq!(
+        stored,
+        Some(ciborium::Value::Tag(
+            24,
+            Box::new(ciborium::Value::Bytes(actions.stdout))
+        ))
+    );
+
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("--ingredient")
+        .arg("--format")
+        .arg("yaml")
+        .assert()
+        .success()
+        .stdout(str::starts_with("title: C.jpg\n"));
+
+    // reports written to a folder take the format's extension
+    let tempdir = tempfile::tempdir()?;
+    let folder = tempdir.path().join("report");
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-d")
+        .arg("--format")
+        .arg("yaml")
+        .arg("-o")
+        .arg(&folder)
+        .assert()
+        .success();
+    assert!(folder.join("manifest_store.yaml").exists());
+    assert!(folder.join("detailed.yaml").exists());
+    Ok(())
+}
+
+#[test]
+fn tool_summary() -> Result<(), Box<dyn Error>> {
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("--summary")
+        .assert()
+        .success()
+        .stdout(str::starts_with("Content credentials for C.jpg\n"))
+        .stdout(str::contains(
+            "Signed by: C2PA Signer (C2PA Test Signing Cert)",
+        ))
+        .stdout(str::contains("  - Created it\n"))
+        .stdout(str::contains("wasn't checked"));
+
+    // trust is checked with the trust subcommand
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("--summary")
+        .arg("trust")
+        .arg("--trust_anchors")
+        .arg(fixture_path("trust/anchors.pem"))
+        .arg("--trust_config")
+        .arg(fixture_path("trust/store.cfg"))
+        .assert()
+        .success()
+        .stdout(str::contains("Valid, and signed by a trusted signer."))
+        .stdout(str::contains("Problems:").not());
+
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("--summary")
+        .arg("trust")
+        .arg("--trust_anchors")
+        .arg(fixture_path("trust/no-match.pem"))
+        .arg("--trust_config")
+        .arg(fixture_path("trust/store.cfg"))
+        .assert()
+        .success()
+        .stdout(str::contains(
+            "Valid, but the signer isn't on the trust list.",
+        ))
+        .stdout(str::contains(
+            "  - The signing certificate isn't issued by anyone on the trust list. \
+             (signingCredential.untrusted)\n",
+        ))
+        .stdout(str::contains("general.error").not());
+    Ok(())
+}
+
+#[test]
+fn tool_extract() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+
+    // an assertion by label is its CBOR data
+    let output = Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("extract")
+        .arg("c2pa.actions")
+        .output()?;
+    assert!(output.status.success());
+    let actions: serde_json::Value = ciborium::from_reader(output.stdout.as_slice())?;
+    assert_eq!(actions["actions"][0]["action"], "c2pa.created");
+
+    // a thumbnail by relative URI is the image
+    let thumbnail = tempdir.path().join("thumbnail.jpg");
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-o")
+        .arg(&thumbnail)
+        .arg("extract")
+        .arg("self#jumbf=c2pa.assertions/c2pa.thumbnail.claim.jpeg")
+        .assert()
+        .success();
+    assert!(fs::read(&thumbnail)?.starts_with(&[0xff, 0xd8]));
+
+    // the store validates as a sidecar of the asset
+    let store = tempdir.path().join("C.c2pa");
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-o")
+        .arg(&store)
+        .arg("extract")
+        .arg("self#jumbf=/c2pa")
+        .assert()
+        .success();
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("--manifest-file")
+        .arg(&store)
+        .assert()
+        .success()
+        .stderr(str::contains("Manifest binding: external"));
+
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("extract")
+        .arg("c2pa.missing")
+        .assert()
+        .failure()
+        .stderr(str::contains("No box labeled \"c2pa.missing\""))
+        .stderr(str::contains("c2pa.actions"));
+    Ok(())
+}
+
+#[test]
+fn tool_extract_remote_manifest() -> Result<(), Box<dyn Error>> {
+    let tempdir = tempfile::tempdir()?;
+    let server = MockServer::start();
+    let output = tempdir.path().join("out.jpg");
+    Command::cargo_bin("c2patool")?
+        .current_dir(tempdir.path())
+        .arg(fixture_path("earth_apollo17.jpg"))
+        .arg("-c")
+        .arg("{}")
+        .arg("-r")
+        .arg(server.url("/manifests/out.c2pa"))
+        .arg("-s")
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+
+    // move the sidecar away, so the store can only come from the remote URL
+    let store = tempdir.path().join("published.c2pa");
+    fs::rename(tempdir.path().join("out.c2pa"), &store)?;
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/manifests/out.c2pa");
+        then.status(200).body_from_file(store.display().to_string());
+    });
+
+    let extracted = Command::cargo_bin("c2patool")?
+        .arg(&output)
+        .arg("extract")
+        .arg("self#jumbf=/c2pa")
+        .output()?;
+    assert!(extracted.status.success());
+    assert_eq!(extracted.stdout, fs::read(&store)?);
+
+    Command::cargo_bin("c2patool")?
+        .arg(&output)
+        .assert()
+        .success()
+        .stderr(str::contains("Manifest binding: remote"));
+    mock.assert_hits(2);
+    Ok(())
+}
+
+#[test]
+fn tool_query() -> Result<(), Box<dyn Error>> {
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("query")
+        .arg(r#"active.assertions["c2pa.actions"].actions[*].action"#)
+        .assert()
+        .success()
+        .stdout("\"c2pa.created\"\n\"c2pa.drawing\"\n");
+
+    // ingredients' manifests are nested in the model
+    let tempdir = tempfile::tempdir()?;
+    let output = tempdir.path().join("out.jpg");
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-c")
+        .arg(r#"{"title": "Out"}"#)
+        .arg("-p")
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    Command::cargo_bin("c2patool")?
+        .arg(&output)
+        .arg("query")
+        .arg("active.ingredients[0].manifest.title")
+        .assert()
+        .success()
+        .stdout("\"C.jpg\"\n");
+
+    Command::cargo_bin("c2patool")?
+        .arg(fixture_path(TEST_IMAGE_WITH_MANIFEST))
+        .arg("query")
+        .arg("active.missing")
+        .assert()
+        .failure()
+        .stderr(str::contains("Nothing matches"));
+    Ok(())
+}